@@ -0,0 +1,53 @@
+use what_anime_shared::cache::{CacheConfig, CacheStats, ResponseCache};
+
+use crate::{AnilistAPI, AnilistAnimeID, Media};
+
+/// Caches the media of another `AnilistAPI` per id, so overlapping batches are only fetched once.
+pub struct CachedAnilistAPI<A> {
+    inner: A,
+    cache: ResponseCache,
+}
+
+impl<A> CachedAnilistAPI<A> {
+    pub fn new(inner: A, config: CacheConfig) -> Self {
+        Self {
+            inner,
+            cache: ResponseCache::new(config),
+        }
+    }
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
+
+fn key(id: AnilistAnimeID) -> String {
+    format!("anilist:media:{}", id.0)
+}
+
+impl<A: AnilistAPI + Sync> AnilistAPI for CachedAnilistAPI<A> {
+    async fn fetch_one(&self, id: AnilistAnimeID) -> Option<Media> {
+        self.fetch_many(vec![id]).await.ok()?.into_iter().next()
+    }
+    async fn fetch_many(
+        &self,
+        ids: Vec<AnilistAnimeID>,
+    ) -> Result<Vec<Media>, what_anime_shared::error::Error> {
+        let mut media = Vec::with_capacity(ids.len());
+        let mut missing = Vec::new();
+        for id in ids {
+            match self.cache.get::<Media>(&key(id)).await {
+                Some(m) => media.push(m),
+                None => missing.push(id),
+            }
+        }
+        if !missing.is_empty() {
+            let fetched = self.inner.fetch_many(missing).await?;
+            for m in &fetched {
+                self.cache.insert(&key(m.id), m).await;
+            }
+            media.extend(fetched);
+        }
+        media.sort_by_key(|m| m.id);
+        Ok(media)
+    }
+}
@@ -1,4 +1,6 @@
+pub mod cached;
 pub mod models;
+pub use cached::CachedAnilistAPI;
 use log::error;
 use log::warn;
 pub use models::Media;
@@ -147,6 +149,15 @@ mod tests {
         assert!(anime.season_year.is_some());
     }
 
+    #[test]
+    fn test_serialize_roundtrip() {
+        // The cache stores media in their serialized form.
+        let animes: Vec<Media> = serde_json::from_str(PARSE_STRING).expect("This should work");
+        let serialized = serde_json::to_string(&animes).unwrap();
+        let parsed: Vec<Media> = serde_json::from_str(&serialized).expect("Roundtrip failed");
+        assert_eq!(format!("{:?}", animes), format!("{:?}", parsed));
+    }
+
     #[tokio::test]
     async fn test_fetch() {
         let api = AnilistAPIR::new();
@@ -3,14 +3,19 @@ use std::vec;
 use super::AnisongAPI;
 use super::models::{Anisong, AnisongArtistID};
 use crate::error::{Error, Result};
+use crate::limiter::{Limiters, RateLimits};
 use crate::models::Release;
 use futures::future::join_all;
 use log::{error, warn};
 use reqwest::StatusCode;
-use reqwest::{Client, Url};
-use serde::{Deserialize, Serialize};
+use reqwest::{Client, Response, Url};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+/// Clones share the rate limits, so every user of one client stays within the same budget.
+#[derive(Clone)]
 pub struct AnisongAPIR {
     client: Client,
+    base_url: Url,
+    limiters: Limiters,
 }
 
 impl AnisongAPI for AnisongAPIR {
@@ -36,129 +41,65 @@ impl AnisongAPI for AnisongAPIR {
             character: true,
         };
 
+        let _permit = self.limiters.artist_ids.acquire().await;
         let response = self
             .client
-            .post(Self::ARTIST_ID_SEARCH_REQUEST_URL)
+            .post(self.endpoint(Self::ARTIST_ID_SEARCH_REQUEST))
             .json(&search)
             .send()
             .await?;
 
-        match response.status() {
-            value if value.is_success() => Ok(response.json().await.unwrap()),
-            StatusCode::SERVICE_UNAVAILABLE | StatusCode::INTERNAL_SERVER_ERROR => {
-                warn!(
-                    "Non-successfull response from anisong, status: {} Response:\n{}",
-                    response.status(),
-                    response.text().await.unwrap(),
-                );
-                Ok(vec![])
-            }
-            _ => {
-                error!(
-                    "Unrecognised non-successfull response from anisong, treated as empty response, status: {} Response:\n{}",
-                    response.status(),
-                    response.text().await.unwrap(),
-                );
-                Ok(vec![])
-            }
-        }
+        Self::parse_response(response).await
     }
-    async fn full_search(&self, song_title: String, mut artist_names: Vec<String>) -> Vec<Anisong> {
-        let mut song_filter = Some(SearchFilter {
-            search: song_title,
-            partial_match: false,
-            max_other_artist: Some(99),
-            group_granularity: Some(0),
-            arrangement: Some(true),
-        });
+    async fn full_search(
+        &self,
+        song_title: String,
+        mut artist_names: Vec<String>,
+    ) -> Result<Vec<Anisong>> {
+        let mut song_filter = Some(
+            SearchFilter::new(song_title)
+                .max_other_artist(99)
+                .group_granularity(0)
+                .arrangement(true),
+        );
 
         let mut futures = Vec::new();
 
         loop {
-            let artist_filter = if let Some(artist_name) = artist_names.pop() {
-                Some(SearchFilter {
-                    search: artist_name,
-                    partial_match: false,
-                    group_granularity: Some(0),
-                    max_other_artist: Some(99),
-                    arrangement: Some(true),
-                })
-            } else {
-                None
-            };
+            let mut search = SearchRequest::new().and_logic(false);
+            if let Some(song_filter) = song_filter.take() {
+                search = search.song(song_filter);
+            }
+            if let Some(artist_name) = artist_names.pop() {
+                let artist_filter = SearchFilter::new(artist_name)
+                    .group_granularity(0)
+                    .max_other_artist(99)
+                    .arrangement(true);
+                search = search.artist(artist_filter.clone()).composer(artist_filter);
+            }
 
-            let search = SearchRequest {
-                anime_search_filter: None,
-                song_name_search_filter: song_filter,
-                artist_search_filter: artist_filter.as_ref().map(|a| a.clone()),
-                composer_search_filter: artist_filter,
-                and_logic: Some(false),
-                ignore_duplicate: Some(false),
-                opening_filter: Some(true),
-                insert_filter: Some(true),
-                ending_filter: Some(true),
-                normal_broadcast: Some(true),
-                dub: Some(true),
-                rebroadcast: Some(true),
-                standard: Some(true),
-                instrumental: Some(true),
-                chanting: Some(true),
-                character: Some(true),
-            };
+            futures.push(self.search(search));
 
-            futures.push(
-                self.client
-                    .post(Self::SEARCH_REQUEST_URL)
-                    .json(&search)
-                    .send(),
-            );
-
-            song_filter = None;
             if artist_names.is_empty() {
                 break;
             }
         }
 
-        let responses = join_all(futures).await;
-
-        let mut anisongs: Vec<Anisong> = Vec::new();
-
-        for result in responses {
-            match result {
-                Ok(response) => {
-                    let mut animes = match response.status() {
-                        status if status.is_success() => match response.json().await {
-                            Ok(animes) => animes,
-                            Err(error) => {
-                                error!("Parsing of animes failed! Error:\n{}", error);
-                                vec![]
-                            }
-                        },
-                        StatusCode::SERVICE_UNAVAILABLE | StatusCode::INTERNAL_SERVER_ERROR => {
-                            warn!(
-                                "Non-successfull response from anisong, status: {} Response:\n{}",
-                                response.status(),
-                                response.text().await.unwrap_or("No text".to_string()),
-                            );
-                            vec![]
-                        }
-                        _ => {
-                            error!(
-                                "Unrecognised non-successfull response from anisong, treated as empty response, status: {} Response:\n{}",
-                                response.status(),
-                                response.text().await.unwrap_or("No text".to_string()),
-                            );
-                            vec![]
-                        }
-                    };
-                    anisongs.append(&mut animes);
-                }
-                Err(error) => {
-                    error!("Anisong fetch failed! error:\n{}", error);
-                }
-            }
-        }
-        anisongs
+        let anisongs = join_all(futures)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<Vec<Anisong>>>>()?;
+        Ok(anisongs.into_iter().flatten().collect())
+    }
+    async fn search(&self, request: SearchRequest) -> Result<Vec<Anisong>> {
+        let _permit = self.limiters.search.acquire().await;
+        let response = self
+            .client
+            .post(self.endpoint(Self::SEARCH_REQUEST))
+            .json(&request)
+            .send()
+            .await?;
+        Self::parse_response(response).await
     }
     async fn get_exact_song(
         &self,
@@ -186,22 +127,15 @@ impl AnisongAPI for AnisongAPIR {
             character: true,
         };
 
+        let _permit = self.limiters.artist_ids.acquire().await;
         let response = self
             .client
-            .post(Self::ARTIST_ID_SEARCH_REQUEST_URL)
+            .post(self.endpoint(Self::ARTIST_ID_SEARCH_REQUEST))
             .json(&search)
             .send()
             .await?;
 
-        let animes: Vec<Anisong> = match response.status() {
-            status if status.is_success() => response.json().await?,
-            status => {
-                return Err(Error::UnsuccessfulResponse {
-                    status,
-                    text: response.text().await.unwrap_or("No text".to_string()),
-                });
-            }
-        };
+        let animes: Vec<Anisong> = Self::parse_response(response).await?;
 
         Ok(animes
             .into_iter()
@@ -210,31 +144,77 @@ impl AnisongAPI for AnisongAPIR {
     }
 
     async fn get_anime_season(&self, release: Release) -> Result<Vec<Anisong>> {
-        let mut url = Url::parse("https://anisongdb.com/api/filter_season").unwrap();
+        let mut url = self.endpoint(Self::FILTER_SEASON);
         url.query_pairs_mut()
             .append_pair("season", &release.to_string());
+        let _permit = self.limiters.filter_season.acquire().await;
         let response = self.client.get(url).send().await?;
-        match response.status() {
-            status if status.is_success() => Ok(response.json().await.unwrap()),
-            status => Err(Error::UnsuccessfulResponse {
-                status,
-                text: response.text().await.unwrap_or("No text".to_string()),
-            }),
-        }
+        Self::parse_response(response).await
     }
 }
 
 impl AnisongAPIR {
-    const SEARCH_REQUEST_URL: &str = "https://anisongdb.com/api/search_request";
-    const ARTIST_ID_SEARCH_REQUEST_URL: &str = "https://anisongdb.com/api/artist_ids_request";
+    pub const DEFAULT_BASE_URL: &str = "https://anisongdb.com/api/";
+    const SEARCH_REQUEST: &str = "search_request";
+    const ARTIST_ID_SEARCH_REQUEST: &str = "artist_ids_request";
+    const FILTER_SEASON: &str = "filter_season";
     pub fn new() -> Self {
+        Self::with_base_url(Url::parse(Self::DEFAULT_BASE_URL).unwrap())
+    }
+    /// Points the client at another anisongdb compatible api, like the local fixture server.
+    pub fn with_base_url(mut base_url: Url) -> Self {
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
         Self {
             client: reqwest::Client::new(),
+            base_url,
+            limiters: Limiters::new(RateLimits::default()),
+        }
+    }
+    pub fn with_rate_limits(mut self, limits: RateLimits) -> Self {
+        self.limiters = Limiters::new(limits);
+        self
+    }
+    /// Uses the `anisong_base_url` environment variable when it's set.
+    pub fn from_env() -> Self {
+        match std::env::var("anisong_base_url") {
+            Ok(url) => {
+                Self::with_base_url(Url::parse(&url).expect("anisong_base_url must be a url"))
+            }
+            Err(_) => Self::new(),
+        }
+    }
+    /// 5xx responses and gateway errors count as the upstream being unavailable.
+    async fn parse_response<T: DeserializeOwned>(response: Response) -> Result<T> {
+        let status = response.status();
+        let text = response.text().await?;
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            warn!(
+                "Anisong unavailable, status: {} Response:\n{}",
+                status, text
+            );
+            return Err(Error::UpstreamUnavailable {
+                status: Some(status),
+                text,
+            });
+        }
+        if !status.is_success() {
+            error!(
+                "Unrecognised non-successfull response from anisong, status: {} Response:\n{}",
+                status, text
+            );
+            return Err(Error::UnsuccessfulResponse { status, text });
         }
+        serde_json::from_str(&text).map_err(|e| Error::from_json(e, &text))
+    }
+    fn endpoint(&self, path: &str) -> Url {
+        self.base_url.join(path).expect("Endpoint paths are valid")
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+/// Query for anisongdb's search, `SearchRequest::new` searches every category.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct SearchRequest {
     anime_search_filter: Option<SearchFilter>,
     song_name_search_filter: Option<SearchFilter>,
@@ -259,6 +239,91 @@ pub struct SearchRequest {
     character: Option<bool>,
 }
 
+impl SearchRequest {
+    pub fn new() -> Self {
+        Self {
+            and_logic: Some(false),
+            ignore_duplicate: Some(false),
+            opening_filter: Some(true),
+            ending_filter: Some(true),
+            insert_filter: Some(true),
+            normal_broadcast: Some(true),
+            dub: Some(true),
+            rebroadcast: Some(true),
+            standard: Some(true),
+            instrumental: Some(true),
+            chanting: Some(true),
+            character: Some(true),
+            ..Default::default()
+        }
+    }
+    pub fn anime(mut self, filter: SearchFilter) -> Self {
+        self.anime_search_filter = Some(filter);
+        self
+    }
+    pub fn song(mut self, filter: SearchFilter) -> Self {
+        self.song_name_search_filter = Some(filter);
+        self
+    }
+    pub fn artist(mut self, filter: SearchFilter) -> Self {
+        self.artist_search_filter = Some(filter);
+        self
+    }
+    pub fn composer(mut self, filter: SearchFilter) -> Self {
+        self.composer_search_filter = Some(filter);
+        self
+    }
+    /// Whether every given filter has to match instead of any of them.
+    pub fn and_logic(mut self, value: bool) -> Self {
+        self.and_logic = Some(value);
+        self
+    }
+    pub fn ignore_duplicate(mut self, value: bool) -> Self {
+        self.ignore_duplicate = Some(value);
+        self
+    }
+    pub fn openings(mut self, value: bool) -> Self {
+        self.opening_filter = Some(value);
+        self
+    }
+    pub fn endings(mut self, value: bool) -> Self {
+        self.ending_filter = Some(value);
+        self
+    }
+    pub fn inserts(mut self, value: bool) -> Self {
+        self.insert_filter = Some(value);
+        self
+    }
+    pub fn normal_broadcast(mut self, value: bool) -> Self {
+        self.normal_broadcast = Some(value);
+        self
+    }
+    pub fn dub(mut self, value: bool) -> Self {
+        self.dub = Some(value);
+        self
+    }
+    pub fn rebroadcast(mut self, value: bool) -> Self {
+        self.rebroadcast = Some(value);
+        self
+    }
+    pub fn standard(mut self, value: bool) -> Self {
+        self.standard = Some(value);
+        self
+    }
+    pub fn instrumental(mut self, value: bool) -> Self {
+        self.instrumental = Some(value);
+        self
+    }
+    pub fn chanting(mut self, value: bool) -> Self {
+        self.chanting = Some(value);
+        self
+    }
+    pub fn character(mut self, value: bool) -> Self {
+        self.character = Some(value);
+        self
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ArtistIDSearchRequest {
     pub artist_ids: Vec<AnisongArtistID>,
@@ -287,3 +352,30 @@ pub struct SearchFilter {
     max_other_artist: Option<i32>,
     arrangement: Option<bool>,
 }
+
+impl SearchFilter {
+    /// Exact match on `search`.
+    pub fn new(search: String) -> Self {
+        Self {
+            search,
+            ..Default::default()
+        }
+    }
+    pub fn partial_match(mut self, value: bool) -> Self {
+        self.partial_match = value;
+        self
+    }
+    pub fn group_granularity(mut self, value: i32) -> Self {
+        self.group_granularity = Some(value);
+        self
+    }
+    pub fn max_other_artist(mut self, value: i32) -> Self {
+        self.max_other_artist = Some(value);
+        self
+    }
+    /// Also search arrangers, only used for artist and composer filters.
+    pub fn arrangement(mut self, value: bool) -> Self {
+        self.arrangement = Some(value);
+        self
+    }
+}
@@ -0,0 +1,16 @@
+use anisong_api::fixtures;
+use tokio::net::TcpListener;
+
+/// Serves the anisong fixtures on the port given as the first argument, 8090 by default.
+#[tokio::main]
+async fn main() {
+    let port = std::env::args().nth(1).unwrap_or("8090".to_string());
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .unwrap();
+    println!(
+        "Serving anisong fixtures, use anisong_base_url={}",
+        fixtures::base_url(listener.local_addr().unwrap())
+    );
+    fixtures::serve(listener).await;
+}
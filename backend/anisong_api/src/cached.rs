@@ -0,0 +1,82 @@
+use serde::Serialize;
+use what_anime_shared::cache::{CacheConfig, CacheStats, ResponseCache};
+
+use crate::{
+    AnisongAPI, SearchRequest,
+    error::Result,
+    models::{Anisong, AnisongArtistID, Release},
+};
+
+/// Caches the responses of another `AnisongAPI`, errors are never cached.
+pub struct CachedAnisongAPI<A> {
+    inner: A,
+    cache: ResponseCache,
+}
+
+impl<A> CachedAnisongAPI<A> {
+    pub fn new(inner: A, config: CacheConfig) -> Self {
+        Self {
+            inner,
+            cache: ResponseCache::new(config),
+        }
+    }
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
+
+fn key<T: Serialize>(method: &str, args: &T) -> String {
+    format!(
+        "anisong:{}:{}",
+        method,
+        serde_json::to_string(args).unwrap()
+    )
+}
+
+impl<A: AnisongAPI + Sync> AnisongAPI for CachedAnisongAPI<A> {
+    async fn artist_id_search(&self, ids: Vec<AnisongArtistID>) -> Result<Vec<Anisong>> {
+        self.cache
+            .get_or_fetch(key("artist_id_search", &ids), || {
+                self.inner.artist_id_search(ids.clone())
+            })
+            .await
+    }
+    async fn full_search(
+        &self,
+        song_title: String,
+        artist_names: Vec<String>,
+    ) -> Result<Vec<Anisong>> {
+        self.cache
+            .get_or_fetch(key("full_search", &(&song_title, &artist_names)), || {
+                self.inner
+                    .full_search(song_title.clone(), artist_names.clone())
+            })
+            .await
+    }
+    async fn search(&self, request: SearchRequest) -> Result<Vec<Anisong>> {
+        self.cache
+            .get_or_fetch(key("search", &request), || {
+                self.inner.search(request.clone())
+            })
+            .await
+    }
+    async fn get_exact_song(
+        &self,
+        song_title: String,
+        artist_ids: Vec<AnisongArtistID>,
+    ) -> Result<Vec<Anisong>> {
+        self.cache
+            .get_or_fetch(key("get_exact_song", &(&song_title, &artist_ids)), || {
+                self.inner
+                    .get_exact_song(song_title.clone(), artist_ids.clone())
+            })
+            .await
+    }
+    async fn get_anime_season(&self, release: Release) -> Result<Vec<Anisong>> {
+        self.cache
+            .get_or_fetch(key("get_anime_season", &release), || {
+                self.inner.get_anime_season(release.clone())
+            })
+            .await
+    }
+}
@@ -1,17 +1,66 @@
 pub type Result<T> = std::result::Result<T, Error>;
 
+const SNIPPET_RADIUS: usize = 100;
+
 #[derive(Debug)]
 pub enum Error {
-    ParseError(String),
+    /// `snippet` is the part of the payload around where parsing failed.
+    ParseError {
+        error: String,
+        snippet: String,
+    },
     ReqwestError(reqwest::Error),
+    /// anisongdb is down, overloaded or unreachable, worth retrying later.
+    UpstreamUnavailable {
+        status: Option<reqwest::StatusCode>,
+        text: String,
+    },
     UnsuccessfulResponse {
         status: reqwest::StatusCode,
         text: String,
     },
 }
 
+impl Error {
+    pub fn is_upstream_unavailable(&self) -> bool {
+        matches!(self, Self::UpstreamUnavailable { .. })
+    }
+
+    pub(crate) fn from_json(error: serde_json::Error, payload: &str) -> Self {
+        // serde_json reports 1 based lines and columns, column 0 means the end of the input.
+        let line_start: usize = payload
+            .split_inclusive('\n')
+            .take(error.line().saturating_sub(1))
+            .map(|l| l.len())
+            .sum();
+        let position = if error.column() == 0 {
+            payload.len()
+        } else {
+            line_start + error.column() - 1
+        };
+        let mut start = position.saturating_sub(SNIPPET_RADIUS).min(payload.len());
+        let mut end = (position + SNIPPET_RADIUS).min(payload.len());
+        while !payload.is_char_boundary(start) {
+            start -= 1;
+        }
+        while !payload.is_char_boundary(end) {
+            end += 1;
+        }
+        Self::ParseError {
+            error: error.to_string(),
+            snippet: payload[start..end].to_string(),
+        }
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(value: reqwest::Error) -> Self {
+        if value.is_connect() || value.is_timeout() {
+            return Self::UpstreamUnavailable {
+                status: None,
+                text: value.to_string(),
+            };
+        }
         Self::ReqwestError(value)
     }
 }
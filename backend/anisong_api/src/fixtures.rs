@@ -0,0 +1,166 @@
+//! Local stand-in for anisongdb.com serving the `testParse*.json` fixtures, so the crate and
+//! the season import can run without the live site.
+use std::net::SocketAddr;
+
+use axum::{
+    Json, Router,
+    extract::Query,
+    routing::{get, post},
+};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::net::TcpListener;
+
+const FIXTURES: [&str; 2] = [
+    include_str!("testParse1.json"),
+    include_str!("testParse2.json"),
+];
+
+fn anisongs() -> Vec<Value> {
+    let mut anisongs: Vec<Value> = Vec::new();
+    for fixture in FIXTURES {
+        let parsed: Vec<Value> = serde_json::from_str(fixture).expect("Fixtures are valid json");
+        for anisong in parsed {
+            let duplicate = anisongs
+                .iter()
+                .any(|a| a["annId"] == anisong["annId"] && a["annSongId"] == anisong["annSongId"]);
+            if !duplicate {
+                anisongs.push(anisong);
+            }
+        }
+    }
+    anisongs
+}
+
+fn artist_names(anisong: &Value, field: &str) -> Vec<String> {
+    anisong[field]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|a| a["names"].as_array().into_iter().flatten())
+        .filter_map(|n| n.as_str().map(|n| n.to_string()))
+        .collect()
+}
+
+fn artist_ids(anisong: &Value, field: &str) -> Vec<i64> {
+    anisong[field]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|a| a["id"].as_i64())
+        .collect()
+}
+
+/// Case insensitive, `partial_match` decides between a substring or a whole value match.
+fn filter_matches(filter: &Value, values: Vec<String>) -> bool {
+    let search = filter["search"].as_str().unwrap_or_default().to_lowercase();
+    let partial = filter["partial_match"].as_bool().unwrap_or(false);
+    values.iter().any(|v| {
+        let v = v.to_lowercase();
+        if partial {
+            v.contains(&search)
+        } else {
+            v == search
+        }
+    })
+}
+
+async fn search_request(Json(request): Json<Value>) -> Json<Vec<Value>> {
+    let and_logic = request["and_logic"].as_bool().unwrap_or(false);
+    let anisongs = anisongs()
+        .into_iter()
+        .filter(|anisong| {
+            let fields = [
+                (
+                    "anime_search_filter",
+                    vec![
+                        anisong["animeENName"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        anisong["animeJPName"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                    ],
+                ),
+                (
+                    "song_name_search_filter",
+                    vec![anisong["songName"].as_str().unwrap_or_default().to_string()],
+                ),
+                ("artist_search_filter", artist_names(anisong, "artists")),
+                ("composer_search_filter", artist_names(anisong, "composers")),
+            ];
+            let mut results = fields
+                .into_iter()
+                .filter(|(filter, _)| !request[filter].is_null())
+                .map(|(filter, values)| filter_matches(&request[filter], values));
+            if and_logic {
+                results.all(|r| r)
+            } else {
+                results.any(|r| r)
+            }
+        })
+        .collect();
+    Json(anisongs)
+}
+
+async fn artist_ids_request(Json(request): Json<Value>) -> Json<Vec<Value>> {
+    let ids: Vec<i64> = request["artist_ids"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|id| id.as_i64())
+        .collect();
+    let anisongs = anisongs()
+        .into_iter()
+        .filter(|anisong| {
+            artist_ids(anisong, "artists")
+                .into_iter()
+                .chain(artist_ids(anisong, "composers"))
+                .any(|id| ids.contains(&id))
+        })
+        .collect();
+    Json(anisongs)
+}
+
+#[derive(Deserialize)]
+struct SeasonParams {
+    season: String,
+}
+
+async fn filter_season(Query(params): Query<SeasonParams>) -> Json<Vec<Value>> {
+    let anisongs = anisongs()
+        .into_iter()
+        .filter(|anisong| anisong["animeVintage"].as_str() == Some(params.season.as_str()))
+        .collect();
+    Json(anisongs)
+}
+
+pub fn router() -> Router {
+    Router::new().nest(
+        "/api",
+        Router::new()
+            .route("/search_request", post(search_request))
+            .route("/artist_ids_request", post(artist_ids_request))
+            .route("/filter_season", get(filter_season)),
+    )
+}
+
+/// Base url to hand to `AnisongAPIR::with_base_url` for a server on `addr`.
+pub fn base_url(addr: SocketAddr) -> Url {
+    Url::parse(&format!("http://{}/api/", addr)).unwrap()
+}
+
+pub async fn serve(listener: TcpListener) {
+    axum::serve(listener, router()).await.unwrap()
+}
+
+/// Starts the server on a free local port in the background.
+pub async fn spawn() -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = base_url(listener.local_addr().unwrap());
+    tokio::spawn(serve(listener));
+    url
+}
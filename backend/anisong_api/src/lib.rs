@@ -1,9 +1,15 @@
 pub mod anisong_api;
+pub mod cached;
 pub mod error;
+#[cfg(any(test, feature = "fixtures"))]
+pub mod fixtures;
+pub mod limiter;
 pub mod models;
 
 use crate::error::Result;
-pub use anisong_api::AnisongAPIR;
+pub use anisong_api::{AnisongAPIR, SearchFilter, SearchRequest};
+pub use cached::CachedAnisongAPI;
+pub use limiter::{EndpointLimit, RateLimits};
 use models::{Anisong, AnisongArtistID, Release};
 
 pub trait AnisongAPI {
@@ -15,7 +21,12 @@ pub trait AnisongAPI {
         &self,
         song_title: String,
         artist_names: Vec<String>,
-    ) -> impl std::future::Future<Output = Vec<Anisong>> + Send;
+    ) -> impl std::future::Future<Output = Result<Vec<Anisong>>> + Send;
+    /// Single upstream search with the filters exactly as given.
+    fn search(
+        &self,
+        request: SearchRequest,
+    ) -> impl std::future::Future<Output = Result<Vec<Anisong>>> + Send;
     fn get_exact_song(
         &self,
         song_title: String,
@@ -32,6 +43,7 @@ mod tests {
     use serde::Deserialize;
 
     use super::*;
+    use what_anime_shared::cache::{CacheConfig, CacheStore};
     const TEST_INPUT: &str = include_str!("testParse1.json");
     const TEST_INPUT2: &str = include_str!("testParse2.json");
     const TEST_INPUT3: &str = include_str!("testParse3.json");
@@ -54,8 +66,132 @@ mod tests {
         assert!(anisong.song.length.is_some());
     }
 
+    #[test]
+    fn test_serialize_roundtrip() {
+        // The cache stores anisongs in their serialized form.
+        let anisongs: Vec<Anisong> = serde_json::from_str(TEST_INPUT2).expect("Parsing Failed");
+        let serialized = serde_json::to_string(&anisongs).unwrap();
+        let parsed: Vec<Anisong> = serde_json::from_str(&serialized).expect("Roundtrip Failed");
+        assert_eq!(format!("{:?}", anisongs), format!("{:?}", parsed));
+    }
+
+    #[test]
+    fn test_parse_error_snippet() {
+        let payload = TEST_INPUT.replacen("\"annSongId\"", "\"annSongId\" oops", 1);
+        let error = serde_json::from_str::<Vec<Anisong>>(&payload).unwrap_err();
+        match error::Error::from_json(error, &payload) {
+            error::Error::ParseError { snippet, .. } => assert!(snippet.contains("oops")),
+            e => panic!("Expected a parse error, got {:?}", e),
+        }
+    }
+
     #[tokio::test]
     async fn test_fetch() {
+        let anisong = AnisongAPIR::with_base_url(fixtures::spawn().await);
+        let a: Vec<Anisong> = anisong
+            .artist_id_search(vec![AnisongArtistID(1134)])
+            .await
+            .unwrap();
+        assert!(!a.is_empty());
+
+        let a = anisong
+            .full_search("Suzume".to_string(), vec![])
+            .await
+            .unwrap();
+        assert!(!a.is_empty());
+
+        let release = Release {
+            season: what_anime_shared::ReleaseSeason::Spring,
+            year: 2008,
+        };
+
+        let a = anisong
+            .get_anime_season(release)
+            .await
+            .expect("Fetch failed");
+        assert!(!a.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let anisong = AnisongAPIR::with_base_url(fixtures::spawn().await);
+        let suzume = SearchFilter::new("suzu".to_string()).partial_match(true);
+
+        let a = anisong
+            .search(SearchRequest::new().song(suzume.clone()))
+            .await
+            .unwrap();
+        assert!(!a.is_empty());
+
+        let a = anisong
+            .search(
+                SearchRequest::new()
+                    .song(suzume)
+                    .artist(SearchFilter::new("Yumi Arai".to_string()))
+                    .and_logic(true),
+            )
+            .await
+            .unwrap();
+        assert!(a.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let limit = EndpointLimit::new(std::time::Duration::from_millis(100), 1);
+        let anisong =
+            AnisongAPIR::with_base_url(fixtures::spawn().await).with_rate_limits(RateLimits {
+                search: limit,
+                artist_ids: limit,
+                filter_season: limit,
+            });
+        let start = std::time::Instant::now();
+        // Each artist name is its own request, so this is three paced requests.
+        anisong
+            .full_search(
+                "Suzume".to_string(),
+                vec![
+                    "RADWIMPS".to_string(),
+                    "Toaka".to_string(),
+                    "Yumi Arai".to_string(),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_cache() {
+        let dir = std::env::temp_dir().join(format!("anisong_cache_test_{}", std::process::id()));
+        let config = CacheConfig {
+            ttl: std::time::Duration::from_secs(60),
+            max_entries: 10,
+            store: CacheStore::Disk(dir.clone()),
+        };
+        let release = Release {
+            season: what_anime_shared::ReleaseSeason::Spring,
+            year: 2008,
+        };
+        let base_url = fixtures::spawn().await;
+
+        let anisong = CachedAnisongAPI::new(AnisongAPIR::with_base_url(base_url), config.clone());
+        let fetched = anisong.get_anime_season(release.clone()).await.unwrap();
+        let cached = anisong.get_anime_season(release.clone()).await.unwrap();
+        assert_eq!(fetched.len(), cached.len());
+        assert_eq!(anisong.stats().hits, 1);
+        assert_eq!(anisong.stats().misses, 1);
+
+        // A fresh instance reads the disk store instead of the unreachable upstream.
+        let unreachable = reqwest::Url::parse("http://127.0.0.1:1/api/").unwrap();
+        let anisong = CachedAnisongAPI::new(AnisongAPIR::with_base_url(unreachable), config);
+        let restored = anisong.get_anime_season(release).await.unwrap();
+        assert_eq!(fetched.len(), restored.len());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "hits the live anisongdb.com"]
+    async fn test_fetch_live() {
         let anisong = AnisongAPIR::new();
         let _: Vec<Anisong> = anisong
             .artist_id_search(vec![AnisongArtistID(1)])
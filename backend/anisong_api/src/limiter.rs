@@ -0,0 +1,91 @@
+//! Request pacing toward anisongdb, clones of an `AnisongAPIR` share the same budget.
+use std::{sync::Arc, time::Duration};
+
+use tokio::{
+    sync::{Mutex, Semaphore, SemaphorePermit},
+    time::{Instant, sleep_until},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct EndpointLimit {
+    /// Minimum time between the starts of two requests.
+    pub min_interval: Duration,
+    pub max_concurrent: usize,
+}
+
+impl EndpointLimit {
+    pub const fn new(min_interval: Duration, max_concurrent: usize) -> Self {
+        Self {
+            min_interval,
+            max_concurrent,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    pub search: EndpointLimit,
+    pub artist_ids: EndpointLimit,
+    /// Season dumps are large, so they are paced the hardest.
+    pub filter_season: EndpointLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            search: EndpointLimit::new(Duration::from_millis(250), 4),
+            artist_ids: EndpointLimit::new(Duration::from_millis(250), 4),
+            filter_season: EndpointLimit::new(Duration::from_secs(6), 1),
+        }
+    }
+}
+
+pub(crate) struct Limiter {
+    min_interval: Duration,
+    permits: Semaphore,
+    next_start: Mutex<Instant>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limit: EndpointLimit) -> Arc<Self> {
+        Arc::new(Self {
+            min_interval: limit.min_interval,
+            permits: Semaphore::new(limit.max_concurrent.max(1)),
+            next_start: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Waits for a free slot, the request should be sent while the permit is held.
+    pub(crate) async fn acquire(&self) -> SemaphorePermit<'_> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("Semaphore is never closed");
+        let start = {
+            let mut next_start = self.next_start.lock().await;
+            let start = (*next_start).max(Instant::now());
+            *next_start = start + self.min_interval;
+            start
+        };
+        sleep_until(start).await;
+        permit
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Limiters {
+    pub search: Arc<Limiter>,
+    pub artist_ids: Arc<Limiter>,
+    pub filter_season: Arc<Limiter>,
+}
+
+impl Limiters {
+    pub(crate) fn new(limits: RateLimits) -> Self {
+        Self {
+            search: Limiter::new(limits.search),
+            artist_ids: Limiter::new(limits.artist_ids),
+            filter_season: Limiter::new(limits.filter_season),
+        }
+    }
+}
@@ -2,7 +2,8 @@ use std::str::FromStr;
 
 use serde::{Deserialize, Deserializer, Serialize, de::Visitor};
 
-use what_anime_shared::{AnilistAnimeID, ReleaseSeason};
+use what_anime_shared::AnilistAnimeID;
+pub use what_anime_shared::Release;
 
 use sqlx::{
     FromRow, Row, Type,
@@ -77,16 +78,46 @@ pub struct AnisongBind {
     pub is_rebroadcast: bool,
 }
 
-#[derive(Serialize, Debug, Clone, FromRow)]
+#[derive(Debug, Clone, FromRow)]
 pub struct Anisong {
-    #[serde(flatten)]
     pub anime: AnisongAnime,
-    #[serde(flatten)]
     pub song: AnisongSong,
-    #[serde(flatten)]
     pub anisong_bind: AnisongBind,
 }
 
+/// Mirrors the deserialize helper, so `annId` only appears once.
+impl Serialize for Anisong {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        #[derive(Serialize)]
+        struct Helper<'a> {
+            #[serde(flatten)]
+            song: &'a AnisongSong,
+            #[serde(flatten)]
+            anime: &'a AnisongAnime,
+            #[serde(rename = "songDifficulty")]
+            difficulty: Option<f64>,
+            #[serde(rename = "songType")]
+            song_type: &'a SongIndex,
+            #[serde(rename = "isRebroadcast")]
+            is_rebroadcast: bool,
+            #[serde(rename = "annSongId")]
+            song_ann_id: SongAnnId,
+        }
+        Helper {
+            song: &self.song,
+            anime: &self.anime,
+            difficulty: self.anisong_bind.difficulty,
+            song_type: &self.anisong_bind.song_type,
+            is_rebroadcast: self.anisong_bind.is_rebroadcast,
+            song_ann_id: self.anisong_bind.song_ann_id,
+        }
+        .serialize(serializer)
+    }
+}
+
 impl<'de> Deserialize<'de> for Anisong {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -215,7 +246,30 @@ impl<'de> Deserialize<'de> for AnimeIndex {
     where
         D: serde::Deserializer<'de>,
     {
-        let s = String::deserialize(deserializer)?;
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Upstream(String),
+            Serialized {
+                index_type: AnimeIndexType,
+                number: i32,
+                part: i16,
+            },
+        }
+        let s = match Repr::deserialize(deserializer)? {
+            Repr::Upstream(s) => s,
+            Repr::Serialized {
+                index_type,
+                number,
+                part,
+            } => {
+                return Ok(AnimeIndex {
+                    index_type,
+                    number,
+                    part,
+                });
+            }
+        };
         let (type_string, index_number): (String, Option<f32>) = split_string(&s);
         let anime_index_type = AnimeIndexType::from_str(&type_string);
 
@@ -245,7 +299,21 @@ impl<'de> Deserialize<'de> for SongIndex {
     where
         D: serde::Deserializer<'de>,
     {
-        let s = String::deserialize(deserializer)?;
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Upstream(String),
+            Serialized {
+                index_type: SongIndexType,
+                number: i32,
+            },
+        }
+        let s = match Repr::deserialize(deserializer)? {
+            Repr::Upstream(s) => s,
+            Repr::Serialized { index_type, number } => {
+                return Ok(SongIndex { index_type, number });
+            }
+        };
         let (type_string, index_number): (String, Option<i32>) = split_string(&s);
         let song_index_type =
             SongIndexType::from_str(&type_string).expect("We should never get bad string :(");
@@ -500,7 +568,10 @@ impl SongIndexType {
             "Opening" => Ok(Self::Opening),
             "Insert Song" => Ok(Self::Insert),
             "Ending" => Ok(Self::Ending),
-            _ => Err(Error::ParseError(s.to_string())),
+            _ => Err(Error::ParseError {
+                error: "Unknown song index type".to_string(),
+                snippet: s.to_string(),
+            }),
         }
     }
 }
@@ -694,35 +765,3 @@ where
 
     deserializer.deserialize_any(BoolOrIntVisitor)
 }
-
-#[derive(Debug, Serialize, Clone)]
-pub struct Release {
-    pub season: ReleaseSeason,
-    pub year: i32,
-}
-impl std::fmt::Display for Release {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", self.season, self.year)
-    }
-}
-
-impl<'de> Deserialize<'de> for Release {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let s = String::deserialize(deserializer)?;
-        let (season_str, year_op) = split_string(&s);
-        if let (Some(year), Ok(season)) = (year_op, ReleaseSeason::from_str(&season_str)) {
-            Ok(Self {
-                season: season,
-                year,
-            })
-        } else {
-            Err(serde::de::Error::custom(format!(
-                "Failed to parse Release from string: {}",
-                s
-            )))
-        }
-    }
-}
@@ -0,0 +1,47 @@
+-- Add migration script here
+ALTER VIEW anisong_view RENAME TO anisong_source_view;
+
+CREATE TABLE anisong_view AS SELECT * FROM anisong_source_view;
+
+ALTER TABLE anisong_view ADD PRIMARY KEY (song_ann_id);
+CREATE INDEX anisong_view_song_id ON anisong_view(song_id);
+CREATE INDEX anisong_view_anime_ann_id ON anisong_view(anime_ann_id);
+CREATE INDEX anisong_view_artist_ids ON anisong_view USING GIN(artist_ids);
+CREATE INDEX anisong_view_composer_ids ON anisong_view USING GIN(composer_ids);
+
+-- Rebuilds every anisong_view row touched by the given songs, animes or artists
+CREATE OR REPLACE FUNCTION refresh_anisong_view(_song_ids INTEGER[], _anime_ids INTEGER[], _artist_ids INTEGER[])
+RETURNS BIGINT AS $$
+DECLARE
+    _song_ann_ids INTEGER[];
+    _refreshed BIGINT;
+BEGIN
+    SELECT COALESCE(array_agg(asl.song_ann_id), '{}') INTO _song_ann_ids
+    FROM anime_song_links asl
+    JOIN songs s ON asl.song_id = s.id
+    WHERE
+        asl.song_id = ANY(_song_ids) OR
+        asl.anime_ann_id = ANY(_anime_ids) OR
+        s.artists && _artist_ids OR
+        s.composers && _artist_ids OR
+        s.arrangers && _artist_ids;
+
+    DELETE FROM anisong_view WHERE song_ann_id = ANY(_song_ann_ids);
+    INSERT INTO anisong_view
+        SELECT * FROM anisong_source_view WHERE song_ann_id = ANY(_song_ann_ids);
+    GET DIAGNOSTICS _refreshed = ROW_COUNT;
+    RETURN _refreshed;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION refresh_anisong_view_all()
+RETURNS BIGINT AS $$
+DECLARE
+    _refreshed BIGINT;
+BEGIN
+    TRUNCATE anisong_view;
+    INSERT INTO anisong_view SELECT * FROM anisong_source_view;
+    GET DIAGNOSTICS _refreshed = ROW_COUNT;
+    RETURN _refreshed;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,39 @@
+-- Add migration script here
+CREATE VIEW anime_view AS SELECT
+    a.ann_id AS anime_ann_id,
+    a.eng_name AS anime_eng_name,
+    a.jpn_name AS anime_jpn_name,
+    a.alt_names AS anime_alt_names,
+    a.vintage_release_season AS anime_vintage_season,
+    a.vintage_release_year AS anime_vintage_year,
+    a.myanimelist_id,
+    a.anidb_id,
+    a.anilist_id,
+    a.kitsu_id,
+    a.anime_type,
+    a.index_type AS anime_index_type,
+    a.index_number AS anime_index_number,
+    a.index_part AS anime_index_part,
+    a.mean_score AS anime_mean_score,
+    a.banner_image AS anime_banner_image,
+    a.cover_image_color AS anime_cover_image_color,
+    a.cover_image_medium AS anime_cover_image_medium,
+    a.cover_image_large AS anime_cover_image_large,
+    a.cover_image_extra_large AS anime_cover_image_extra_large,
+    a.format AS anime_format,
+    a.genres AS anime_genres,
+    a.source AS anime_source,
+    a.studios_id AS anime_studios_id,
+    a.studios_name AS anime_studios_name,
+    a.studios_url AS anime_studios_url,
+    a.tags_id AS anime_tags_id,
+    a.tags_name AS anime_tags_name,
+    a.trailer_id AS anime_trailer_id,
+    a.trailer_site AS anime_trailer_site,
+    a.trailer_thumbnail AS anime_trailer_thumbnail,
+    a.episodes AS anime_episodes,
+    a.season AS anime_season,
+    a.season_year AS anime_season_year
+FROM animes a;
+
+CREATE INDEX idx_anime_anilist_id ON animes(anilist_id);
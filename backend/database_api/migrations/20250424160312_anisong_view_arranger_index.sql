@@ -0,0 +1,2 @@
+-- Add migration script here
+CREATE INDEX anisong_view_arranger_ids ON anisong_view USING GIN(arranger_ids);
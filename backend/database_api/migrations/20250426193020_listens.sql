@@ -0,0 +1,16 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS listens (
+    listen_id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    user_id VARCHAR(32) NOT NULL,
+    track_id VARCHAR(22) NOT NULL,
+    track_name TEXT NOT NULL,
+    artist_names TEXT[] NOT NULL,
+    -- Match result, song and anime are the best hit when there is one --
+    hit BOOLEAN NOT NULL,
+    certainty INTEGER,
+    song_id INTEGER,
+    anime_ann_id INTEGER,
+    listened_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_listens_user ON listens(user_id, listened_at DESC);
@@ -0,0 +1,26 @@
+-- Add migration script here
+-- One resumable scan per user, saved_offset is NULL once the saved tracks are done
+CREATE TABLE IF NOT EXISTS library_scans (
+    user_id VARCHAR(32) PRIMARY KEY,
+    auto_bind BOOLEAN NOT NULL DEFAULT FALSE,
+    saved_offset INTEGER DEFAULT 0,
+    playlist_offset INTEGER NOT NULL DEFAULT 0,
+    track_offset INTEGER NOT NULL DEFAULT 0,
+    tracks_scanned INTEGER NOT NULL DEFAULT 0,
+    finished BOOLEAN NOT NULL DEFAULT FALSE,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS library_scan_hits (
+    user_id VARCHAR(32) NOT NULL,
+    track_id VARCHAR(22) NOT NULL,
+    track_name TEXT NOT NULL,
+    artist_names TEXT[] NOT NULL,
+    song_id INTEGER NOT NULL,
+    anime_ann_id INTEGER NOT NULL,
+    certainty INTEGER NOT NULL,
+    -- 'saved' or the playlist name --
+    found_in TEXT NOT NULL,
+    PRIMARY KEY (user_id, track_id)
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE spotify_song_links ADD COLUMN IF NOT EXISTS isrc VARCHAR(12);
+
+CREATE INDEX IF NOT EXISTS spotify_song_links_isrc ON spotify_song_links(isrc);
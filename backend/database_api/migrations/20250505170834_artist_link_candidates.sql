@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS spotify_artist_link_candidates (
+    spotify_id VARCHAR(22) NOT NULL,
+    artist_id INTEGER NOT NULL,
+    -- pair score [0, 100] of the best confirmed song bind that proposed it --
+    confidence REAL NOT NULL,
+    song_id INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (spotify_id, artist_id)
+);
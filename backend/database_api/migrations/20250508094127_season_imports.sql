@@ -0,0 +1,23 @@
+-- Add migration script here
+-- Last season every named import completed, an import resumes after it
+CREATE TABLE IF NOT EXISTS season_import_checkpoints (
+    name TEXT PRIMARY KEY,
+    season release_season NOT NULL,
+    year INTEGER NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS season_imports (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    season release_season NOT NULL,
+    year INTEGER NOT NULL,
+    attempts INTEGER NOT NULL,
+    songs INTEGER NOT NULL,
+    summary JSONB NOT NULL,
+    -- last failure when every attempt failed --
+    error TEXT,
+    finished_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS season_imports_name_idx ON season_imports (name, finished_at);
@@ -0,0 +1,43 @@
+-- Add migration script here
+-- sort_int_array returned NULL for an empty array, so the unique index on songs never
+-- matched songs without artists and every import added another copy of them.
+
+-- Folds the copies into the oldest one before the index can enforce it again
+CREATE TEMP TABLE song_duplicates ON COMMIT DROP AS
+SELECT s.id, keep.id AS keep_id
+FROM songs s
+JOIN LATERAL (
+    SELECT MIN(k.id) AS id
+    FROM songs k
+    WHERE k.name = s.name AND cardinality(k.artists) = 0
+) keep ON true
+WHERE cardinality(s.artists) = 0 AND s.id <> keep.id;
+
+UPDATE anime_song_links asl SET song_id = d.keep_id
+FROM song_duplicates d WHERE asl.song_id = d.id;
+
+INSERT INTO spotify_song_links (spotify_id, song_id, isrc)
+SELECT l.spotify_id, d.keep_id, l.isrc
+FROM spotify_song_links l JOIN song_duplicates d ON l.song_id = d.id
+ON CONFLICT DO NOTHING;
+DELETE FROM spotify_song_links l USING song_duplicates d WHERE l.song_id = d.id;
+
+UPDATE spotify_artist_link_candidates c SET song_id = d.keep_id
+FROM song_duplicates d WHERE c.song_id = d.id;
+UPDATE listens l SET song_id = d.keep_id
+FROM song_duplicates d WHERE l.song_id = d.id;
+UPDATE library_scan_hits h SET song_id = d.keep_id
+FROM song_duplicates d WHERE h.song_id = d.id;
+
+DELETE FROM songs s USING song_duplicates d WHERE s.id = d.id;
+
+CREATE OR REPLACE FUNCTION sort_int_array(_arr INTEGER[])
+RETURNS INTEGER[] AS $$
+SELECT COALESCE(array_agg(elem ORDER BY elem), '{}')
+FROM unnest(_arr) AS elem;
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- The index stores the old results of the function
+REINDEX INDEX unique_song_artists_name;
+
+SELECT refresh_anisong_view_all();
@@ -0,0 +1,186 @@
+//! Seeds the database from a local dump of anisongdb records, either one json array or json lines.
+//! The file is parsed on a blocking thread and handed over in chunks, so it never has to fit in
+//! memory at once.
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+};
+
+use anilist_api::AnilistAPI;
+use anisong_api::models::Anisong;
+use serde::{
+    Deserializer,
+    de::{SeqAccess, Visitor},
+};
+use tokio::sync::mpsc;
+
+use crate::{Database, models::ImportSummary, season_import::import_with_media};
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl From<std::io::Error> for DumpError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for DumpError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Parse(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DumpProgress {
+    pub records: usize,
+    pub chunks: usize,
+    pub summary: ImportSummary,
+}
+
+struct ChunkSender<'a> {
+    sender: &'a mpsc::Sender<Result<Vec<Anisong>, DumpError>>,
+    chunk_size: usize,
+}
+
+impl<'de> Visitor<'de> for ChunkSender<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an array of anisongs")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<(), S::Error> {
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+        while let Some(anisong) = seq.next_element::<Anisong>()? {
+            chunk.push(anisong);
+            if chunk.len() == self.chunk_size {
+                let full = std::mem::replace(&mut chunk, Vec::with_capacity(self.chunk_size));
+                // A closed channel means the import stopped, nothing left to do.
+                if self.sender.blocking_send(Ok(full)).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        if !chunk.is_empty() {
+            let _ = self.sender.blocking_send(Ok(chunk));
+        }
+        Ok(())
+    }
+}
+
+fn send_chunks(
+    path: PathBuf,
+    chunk_size: usize,
+    sender: &mpsc::Sender<Result<Vec<Anisong>, DumpError>>,
+) -> Result<(), DumpError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let first = loop {
+        let buf = reader.fill_buf()?;
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(i) => {
+                let first = buf[i];
+                reader.consume(i);
+                break Some(first);
+            }
+            None if buf.is_empty() => break None,
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    };
+
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    if first == Some(b'[') {
+        de.deserialize_seq(ChunkSender { sender, chunk_size })?;
+        return Ok(());
+    }
+    let mut chunk = Vec::with_capacity(chunk_size);
+    for anisong in de.into_iter::<Anisong>() {
+        chunk.push(anisong?);
+        if chunk.len() == chunk_size {
+            let full = std::mem::replace(&mut chunk, Vec::with_capacity(chunk_size));
+            if sender.blocking_send(Ok(full)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        let _ = sender.blocking_send(Ok(chunk));
+    }
+    Ok(())
+}
+
+/// Parses the dump in the background, yielding chunks of at most `chunk_size` records.
+pub fn read_dump(
+    path: PathBuf,
+    chunk_size: usize,
+) -> mpsc::Receiver<Result<Vec<Anisong>, DumpError>> {
+    let (sender, receiver) = mpsc::channel(2);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = send_chunks(path, chunk_size.max(1), &sender) {
+            let _ = sender.blocking_send(Err(e));
+        }
+    });
+    receiver
+}
+
+/// Imports every record of the dump along with the anilist media of their anime. `progress` is
+/// called after every chunk, chunks before a parse error stay imported.
+pub async fn import_dump<D, B>(
+    db: &D,
+    anilist: &B,
+    path: PathBuf,
+    chunk_size: usize,
+    mut progress: impl FnMut(&DumpProgress),
+) -> Result<DumpProgress, DumpError>
+where
+    D: Database,
+    B: AnilistAPI,
+{
+    let mut chunks = read_dump(path, chunk_size);
+    let mut done = DumpProgress::default();
+    while let Some(chunk) = chunks.recv().await {
+        let chunk = chunk?;
+        done.records += chunk.len();
+        done.chunks += 1;
+        done.summary += import_with_media(db, anilist, chunk).await;
+        progress(&done);
+    }
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_dump() {
+        const DUMP: &str = include_str!("../../anisong_api/src/testParse2.json");
+        let anisongs: Vec<serde_json::Value> = serde_json::from_str(DUMP).unwrap();
+        let lines: Vec<String> = anisongs.iter().map(|a| a.to_string()).collect();
+
+        let dir = std::env::temp_dir();
+        let array = dir.join(format!("anisong_dump_{}.json", std::process::id()));
+        let jsonl = dir.join(format!("anisong_dump_{}.jsonl", std::process::id()));
+        std::fs::write(&array, DUMP).unwrap();
+        std::fs::write(&jsonl, lines.join("\n")).unwrap();
+
+        for path in [array, jsonl] {
+            let mut chunks = read_dump(path.clone(), 3);
+            let mut records = 0;
+            while let Some(chunk) = chunks.recv().await {
+                let chunk = chunk.unwrap();
+                assert!(chunk.len() <= 3);
+                records += chunk.len();
+            }
+            assert_eq!(records, anisongs.len());
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -1,46 +1,84 @@
 use std::collections::{HashMap, HashSet};
 use std::env;
 
+use chrono::{DateTime, Utc};
+
 use anilist_api::Media;
-use anilist_api::models::TagID;
 
-use anisong_api::models::{Anisong, AnisongAnime, AnisongArtistID, AnisongBind, AnisongSong};
+use anisong_api::models::{
+    Anisong, AnisongAnime, AnisongArtistID, AnisongBind, AnisongSong, AnnAnimeID, Release,
+};
 
-use models::{DBAnime, DBAnisong, DBAnisongBind, Report, SimplifiedAnisongSong, SimplifiedArtist};
+use models::{
+    AnimeLookup, ArtistLinkCandidate, DBAnime, DBAnisong, DBAnisongBind, DBArtist, DBListen,
+    DBScanHit, ImportSummary, LibraryScan, Listen, Page, Paged, PlaylistSong, PlaylistSource,
+    Report, ScanHit, SeasonImport, SeasonStat, SimplifiedAnisongSong, SimplifiedArtist,
+    SnapshotTable, SongTypeStat, StatCount, UserStats,
+};
 
-use sqlx::QueryBuilder;
+use futures::StreamExt;
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, QueryBuilder, Row, ValueRef};
 // use sqlx::migrate;
 use sqlx::{self, Postgres, postgres::PgPoolOptions};
-use what_anime_shared::{SongID, SpotifyArtistID, SpotifyTrackID, SpotifyUserID, URL};
+use what_anime_shared::{Isrc, SongID, SpotifyArtistID, SpotifyTrackID, SpotifyUserID};
 
 use crate::models::DBUser;
+use crate::snapshot::{SnapshotError, SnapshotSource};
 
+pub mod dump;
 pub mod models;
 pub mod regex;
+pub mod season_import;
+pub mod snapshot;
+mod upsert;
 pub trait Database {
+    // Passing `None` as page fetches every match.
     fn get_anisongs_by_song_id(
         &self,
         song_id: SpotifyTrackID,
-    ) -> impl std::future::Future<Output = Vec<DBAnisong>> + Send;
+        page: Option<Page>,
+    ) -> impl std::future::Future<Output = Paged<DBAnisong>> + Send;
+    /// Resolves through any spotify track already linked with the same ISRC.
+    fn get_anisongs_by_isrc(
+        &self,
+        isrc: Isrc,
+        page: Option<Page>,
+    ) -> impl std::future::Future<Output = Paged<DBAnisong>> + Send;
     fn get_anisongs_by_artist_ids(
         &self,
         artist_ids: Vec<SpotifyArtistID>,
-    ) -> impl std::future::Future<Output = Vec<DBAnisong>> + Send;
+        page: Option<Page>,
+    ) -> impl std::future::Future<Output = Paged<DBAnisong>> + Send;
+    /// Songs in `exclude_song_ids` are left out before paging, so the pages stay contiguous.
     fn get_anisongs_by_ani_artist_ids(
         &self,
         artist_ids: Vec<AnisongArtistID>,
-    ) -> impl std::future::Future<Output = Vec<DBAnisong>> + Send;
+        exclude_song_ids: Vec<SongID>,
+        page: Option<Page>,
+    ) -> impl std::future::Future<Output = Paged<DBAnisong>> + Send;
     fn get_artists(
         &self,
         artist_ids: Vec<AnisongArtistID>,
     ) -> impl std::future::Future<Output = Vec<SimplifiedArtist>> + Send;
+    /// Artist with resolved groups and members, spotify links and discography by role.
+    fn get_artist(
+        &self,
+        artist_id: AnisongArtistID,
+    ) -> impl std::future::Future<Output = Option<DBArtist>> + Send;
     fn bind_artists(
         &self,
         binds: Vec<(AnisongArtistID, SpotifyArtistID)>,
     ) -> impl std::future::Future<Output = u64> + Send;
+    /// Fills in the ISRC of already existing links when it's given.
     fn bind_songs(
         &self,
-        binds: Vec<(SongID, SpotifyTrackID)>,
+        binds: Vec<(SongID, SpotifyTrackID, Option<Isrc>)>,
+    ) -> impl std::future::Future<Output = u64> + Send;
+    /// Keeps the highest confidence seen per pair, pairs that are already bound are skipped.
+    fn add_artist_link_candidates(
+        &self,
+        candidates: Vec<ArtistLinkCandidate>,
     ) -> impl std::future::Future<Output = u64> + Send;
     fn add_artists(
         &self,
@@ -59,7 +97,9 @@ pub trait Database {
         &self,
         anisongs: Vec<Anisong>,
         media: Vec<Media>,
-    ) -> impl std::future::Future<Output = ()> + Send;
+    ) -> impl std::future::Future<Output = ImportSummary> + Send;
+    /// Rebuilds the whole `anisong_view` table from its source tables.
+    fn refresh_anisong_view(&self) -> impl std::future::Future<Output = u64> + Send;
     fn add_report(&self, report: Report) -> impl std::future::Future<Output = ()> + Send;
     fn full_search(
         &self,
@@ -67,14 +107,113 @@ pub trait Database {
         artist_names: Vec<String>,
         whole_word_match: bool,
         case_sensitive: bool,
+        page: Option<Page>,
+    ) -> impl std::future::Future<Output = Paged<DBAnisong>> + Send;
+    fn get_anime(
+        &self,
+        lookup: AnimeLookup,
+    ) -> impl std::future::Future<Output = Option<DBAnime>> + Send;
+    /// All songs of an anime ordered openings first, then endings, then inserts.
+    fn get_songs_for_anime(
+        &self,
+        ann_id: AnnAnimeID,
     ) -> impl std::future::Future<Output = Vec<DBAnisong>> + Send;
+    /// Regex search across english, japanese and alternative anime names.
+    fn search_anime(
+        &self,
+        name: String,
+        page: Page,
+    ) -> impl std::future::Future<Output = Paged<DBAnime>> + Send;
     fn get_user(
         &self,
         user_id: SpotifyUserID,
     ) -> impl std::future::Future<Output = Option<DBUser>> + Send;
     fn add_user(&self, user: DBUser) -> impl std::future::Future<Output = Result<(), ()>> + Send;
+    fn add_listen(
+        &self,
+        user_id: SpotifyUserID,
+        listen: Listen,
+    ) -> impl std::future::Future<Output = ()> + Send;
+    /// Newest first, `hits_only` skips listens that didn't match an anime.
+    fn get_listens(
+        &self,
+        user_id: SpotifyUserID,
+        hits_only: bool,
+        page: Page,
+    ) -> impl std::future::Future<Output = Paged<DBListen>> + Send;
+    /// Aggregates the listens since `since`, or all of them. Each breakdown is capped at `limit`.
+    fn get_user_stats(
+        &self,
+        user_id: SpotifyUserID,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> impl std::future::Future<Output = UserStats> + Send;
+    /// Every song of the source once, bound or not, in airing order.
+    fn get_playlist_songs(
+        &self,
+        source: PlaylistSource,
+    ) -> impl std::future::Future<Output = Vec<PlaylistSong>> + Send;
+    fn get_library_scan(
+        &self,
+        user_id: SpotifyUserID,
+    ) -> impl std::future::Future<Output = Option<LibraryScan>> + Send;
+    /// Resets the user's scan progress and drops the hits of any earlier scan.
+    fn start_library_scan(
+        &self,
+        user_id: SpotifyUserID,
+        auto_bind: bool,
+    ) -> impl std::future::Future<Output = LibraryScan> + Send;
+    /// Stores the progress and the hits of one scanned page together.
+    fn save_library_scan(
+        &self,
+        scan: LibraryScan,
+        hits: Vec<ScanHit>,
+    ) -> impl std::future::Future<Output = ()> + Send;
+    fn get_library_scan_hits(
+        &self,
+        user_id: SpotifyUserID,
+        page: Page,
+    ) -> impl std::future::Future<Output = Paged<DBScanHit>> + Send;
+    /// Last season the named import completed.
+    fn get_import_checkpoint(
+        &self,
+        name: String,
+    ) -> impl std::future::Future<Output = Option<Release>> + Send;
+    /// Rows as json objects keyed by column, in primary key order. Continues after the key of
+    /// `after`, the last row of the previous page.
+    fn export_rows(
+        &self,
+        table: SnapshotTable,
+        after: Option<serde_json::Value>,
+        limit: i64,
+    ) -> impl std::future::Future<Output = Vec<serde_json::Value>> + Send;
+    /// Streams the whole table in postgres' csv format, with a header.
+    fn export_csv<W: std::io::Write + Send>(
+        &self,
+        table: SnapshotTable,
+        out: &mut W,
+    ) -> impl std::future::Future<Output = std::io::Result<()>> + Send;
+    /// Restores the tables in order within one transaction. The rows keep their ids, so every
+    /// table has to be empty. Returns how many rows were added per table.
+    fn restore_snapshot(
+        &self,
+        tables: Vec<(SnapshotTable, SnapshotSource)>,
+    ) -> impl std::future::Future<Output = Result<Vec<(SnapshotTable, u64)>, SnapshotError>> + Send;
+    /// Stores the stats of the season, a successful import also moves the checkpoint to it.
+    fn save_season_import(
+        &self,
+        import: SeasonImport,
+    ) -> impl std::future::Future<Output = ()> + Send;
 }
 
+/// Prefix for the stat queries, `$1` is the user and `$2` the optional start of the window.
+const USER_LISTENS: &str = r#"
+    WITH l AS (
+        SELECT * FROM listens
+        WHERE user_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR listened_at >= $2)
+    )
+"#;
+
 pub struct DatabaseR {
     pub pool: sqlx::Pool<sqlx::Postgres>,
 }
@@ -100,25 +239,70 @@ impl DatabaseR {
 
         Self { pool }
     }
+
+    /// Runs a `StatCount` query over `USER_LISTENS`, `$3` is the limit.
+    async fn stat_counts(
+        &self,
+        query: &str,
+        user_id: &SpotifyUserID,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Vec<StatCount> {
+        sqlx::query_as::<Postgres, StatCount>(&format!("{USER_LISTENS}{query}"))
+            .bind(user_id)
+            .bind(since)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
 }
 
 impl Database for DatabaseR {
-    async fn get_anisongs_by_artist_ids(&self, artist_ids: Vec<SpotifyArtistID>) -> Vec<DBAnisong> {
+    async fn get_anisongs_by_artist_ids(
+        &self,
+        artist_ids: Vec<SpotifyArtistID>,
+        page: Option<Page>,
+    ) -> Paged<DBAnisong> {
         if artist_ids.is_empty() {
-            return vec![];
+            return Paged::default();
         }
-        sqlx::query_as::<Postgres, DBAnisong>(ANI_SONGS_FROM_SPOTIFY_ARTISTS)
+        let rows = sqlx::query::<Postgres>(ANI_SONGS_FROM_SPOTIFY_ARTISTS)
             .bind(artist_ids)
+            .bind(page.map(|p| p.limit))
+            .bind(page.map(|p| p.offset))
             .fetch_all(&self.pool)
             .await
-            .unwrap()
+            .unwrap();
+        paged_anisongs(rows)
     }
-    async fn get_anisongs_by_song_id(&self, song_id: SpotifyTrackID) -> Vec<DBAnisong> {
-        sqlx::query_as::<Postgres, DBAnisong>(ANI_SONGS_FROM_SPOTIFY_SONG)
+    async fn get_anisongs_by_song_id(
+        &self,
+        song_id: SpotifyTrackID,
+        page: Option<Page>,
+    ) -> Paged<DBAnisong> {
+        let rows = sqlx::query::<Postgres>(ANI_SONGS_FROM_SPOTIFY_SONG)
             .bind(song_id)
+            .bind(page.map(|p| p.limit))
+            .bind(page.map(|p| p.offset))
             .fetch_all(&self.pool)
             .await
-            .unwrap()
+            .unwrap();
+        paged_anisongs(rows)
+    }
+
+    async fn get_anisongs_by_isrc(&self, isrc: Isrc, page: Option<Page>) -> Paged<DBAnisong> {
+        let spotify_id = sqlx::query_scalar::<Postgres, SpotifyTrackID>(
+            "SELECT spotify_id FROM spotify_song_links WHERE isrc = $1 LIMIT 1",
+        )
+        .bind(isrc)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap();
+        match spotify_id {
+            Some(id) => self.get_anisongs_by_song_id(id, page).await,
+            None => Paged::default(),
+        }
     }
 
     async fn get_artists(&self, artist_ids: Vec<AnisongArtistID>) -> Vec<SimplifiedArtist> {
@@ -137,16 +321,81 @@ impl Database for DatabaseR {
         .await
         .unwrap()
     }
-    async fn bind_songs(&self, binds: Vec<(SongID, SpotifyTrackID)>) -> u64 {
+    async fn get_artist(&self, artist_id: AnisongArtistID) -> Option<DBArtist> {
+        let artist = self.get_artists(vec![artist_id]).await.into_iter().next()?;
+        let groups = self.get_artists(artist.group_ids.clone()).await;
+        let members = self.get_artists(artist.member_ids.clone()).await;
+
+        let spotify_ids = sqlx::query_scalar::<Postgres, SpotifyArtistID>(
+            "SELECT spotify_id FROM spotify_artist_links WHERE artist_id = $1",
+        )
+        .bind(artist_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap();
+
+        let rows = sqlx::query::<Postgres>(
+            r#"
+            SELECT *,
+                s.artist_ids && ARRAY[$1] AS is_performer,
+                s.composer_ids && ARRAY[$1] AS is_composer,
+                s.arranger_ids && ARRAY[$1] AS is_arranger
+            FROM anisong_view s
+            WHERE
+                s.artist_ids && ARRAY[$1] OR
+                s.composer_ids && ARRAY[$1] OR
+                s.arranger_ids && ARRAY[$1]
+            ORDER BY s.anime_vintage_year NULLS LAST, s.song_id, s.song_ann_id;
+            "#,
+        )
+        .bind(artist_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap();
+
+        let mut performed = Vec::new();
+        let mut composed = Vec::new();
+        let mut arranged = Vec::new();
+        for row in rows {
+            let anisong = DBAnisong::from_row(&row).unwrap();
+            if row.get("is_performer") {
+                performed.push(anisong.clone());
+            }
+            if row.get("is_composer") {
+                composed.push(anisong.clone());
+            }
+            if row.get("is_arranger") {
+                arranged.push(anisong);
+            }
+        }
+
+        Some(DBArtist {
+            artist,
+            groups,
+            members,
+            spotify_ids,
+            performed,
+            composed,
+            arranged,
+        })
+    }
+    async fn bind_songs(&self, binds: Vec<(SongID, SpotifyTrackID, Option<Isrc>)>) -> u64 {
         if binds.is_empty() {
             return 0;
         }
         let mut query_builder: QueryBuilder<'_, Postgres> =
-            QueryBuilder::new("INSERT INTO spotify_song_links (song_id, spotify_id) ");
+            QueryBuilder::new("INSERT INTO spotify_song_links (song_id, spotify_id, isrc) ");
         query_builder.push_values(binds, |mut builder, value| {
-            builder.push_bind(value.0).push_bind(value.1);
+            builder
+                .push_bind(value.0)
+                .push_bind(value.1)
+                .push_bind(value.2);
         });
-        query_builder.push(" ON CONFLICT DO NOTHING");
+        query_builder.push(
+            " ON CONFLICT (spotify_id, song_id) DO UPDATE SET isrc = EXCLUDED.isrc
+            WHERE EXCLUDED.isrc IS NOT NULL
+            AND spotify_song_links.isrc IS DISTINCT FROM EXCLUDED.isrc",
+        );
         query_builder
             .build()
             .execute(&self.pool)
@@ -171,107 +420,31 @@ impl Database for DatabaseR {
             .unwrap()
             .rows_affected()
     }
-    async fn add_animes(&self, animes: Vec<DBAnime>) -> u64 {
-        if animes.is_empty() {
+    async fn add_artist_link_candidates(&self, candidates: Vec<ArtistLinkCandidate>) -> u64 {
+        if candidates.is_empty() {
             return 0;
         }
-        let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
-            r#"INSERT INTO animes (
-            ann_id, eng_name, jpn_name, alt_names, myanimelist_id, anidb_id, anilist_id, kitsu_id, anime_type, index_type, index_number,
-            index_part, mean_score, banner_image, cover_image_color, cover_image_medium, cover_image_large, cover_image_extra_large, format,
-            genres, source, studios_id, studios_name, studios_url, tags_id, tags_name, trailer_id, trailer_site, trailer_thumbnail, episodes,
-            season, season_year, vintage_release_season, vintage_release_year
-            )
-            "#,
+        let mut query_builder: QueryBuilder<'_, Postgres> = QueryBuilder::new(
+            "INSERT INTO spotify_artist_link_candidates (artist_id, spotify_id, confidence, song_id) 
+            SELECT * FROM (",
         );
-        query_builder.push_values(animes, |mut builder, anime| {
-            let (tag_ids, tag_names): (Vec<TagID>, Vec<String>) =
-                anime.tags.into_iter().map(|t| (t.id, t.name)).unzip();
-            let (studio_ids, studio_info): (Vec<i32>, Vec<(String, Option<URL>)>) = anime
-                .studios
-                .nodes
-                .into_iter()
-                .map(|a| (a.id, (a.name, a.site_url)))
-                .unzip();
-
-            let (studio_names, studio_urls): (Vec<String>, Vec<Option<URL>>) =
-                studio_info.into_iter().unzip();
-
+        query_builder.push_values(candidates, |mut builder, value| {
             builder
-                .push_bind(anime.ann_id)
-                .push_bind(anime.eng_name)
-                .push_bind(anime.jpn_name)
-                .push_bind(anime.alt_name)
-                .push_bind(anime.linked_ids.myanimelist)
-                .push_bind(anime.linked_ids.anidb)
-                .push_bind(anime.linked_ids.anilist)
-                .push_bind(anime.linked_ids.kitsu)
-                .push_bind(anime.anime_type)
-                .push_bind(anime.anime_index.index_type)
-                .push_bind(anime.anime_index.number)
-                .push_bind(anime.anime_index.part)
-                .push_bind(anime.mean_score)
-                .push_bind(anime.banner_image)
-                .push_bind(anime.cover_image.color)
-                .push_bind(anime.cover_image.medium)
-                .push_bind(anime.cover_image.large)
-                .push_bind(anime.cover_image.extra_large)
-                .push_bind(anime.format)
-                .push_bind(anime.genres)
-                .push_bind(anime.source)
-                .push_bind(studio_ids)
-                .push_bind(studio_names)
-                .push_bind(studio_urls)
-                .push_bind(tag_ids)
-                .push_bind(tag_names)
-                .push_bind(anime.trailer.as_ref().map(|t| t.id.clone()))
-                .push_bind(anime.trailer.as_ref().map(|t| t.site.clone()))
-                .push_bind(anime.trailer.as_ref().map(|t| t.thumbnail.clone()))
-                .push_bind(anime.episodes)
-                .push_bind(anime.season)
-                .push_bind(anime.season_year)
-                .push_bind(anime.vintage.as_ref().map(|v| v.season.clone()))
-                .push_bind(anime.vintage.map(|v| v.year));
+                .push_bind(value.artist_id)
+                .push_bind(value.spotify_id)
+                .push_bind(value.confidence)
+                .push_bind(value.song_id);
         });
-
         query_builder.push(
-            r#" ON CONFLICT ( ann_id ) DO UPDATE SET
-            eng_name = COALESCE(EXCLUDED.eng_name, animes.eng_name),
-            jpn_name = COALESCE(EXCLUDED.jpn_name, animes.jpn_name), 
-            alt_names = array_unique(EXCLUDED.alt_names, animes.alt_names), 
-            myanimelist_id = COALESCE(EXCLUDED.myanimelist_id, animes.myanimelist_id), 
-            anidb_id = COALESCE(EXCLUDED.anidb_id, animes.anidb_id), 
-            anilist_id = COALESCE(EXCLUDED.anilist_id, animes.anilist_id), 
-            kitsu_id = COALESCE(EXCLUDED.kitsu_id, animes.kitsu_id), 
-            anime_type = COALESCE(EXCLUDED.anime_type, animes.anime_type), 
-            index_type = COALESCE(EXCLUDED.index_type, animes.index_type), 
-            index_number = COALESCE(EXCLUDED.index_number, animes.index_number),
-            index_part = COALESCE(EXCLUDED.index_part, animes.index_part), 
-            mean_score = COALESCE(EXCLUDED.mean_score, animes.mean_score), 
-            banner_image = COALESCE(EXCLUDED.banner_image, animes.banner_image), 
-            cover_image_color = COALESCE(EXCLUDED.cover_image_color, animes.cover_image_color), 
-            cover_image_medium = COALESCE(EXCLUDED.cover_image_medium, animes.cover_image_medium), 
-            cover_image_large = COALESCE(EXCLUDED.cover_image_large, animes.cover_image_large), 
-            cover_image_extra_large = COALESCE(EXCLUDED.cover_image_extra_large, animes.cover_image_extra_large), 
-            format = COALESCE(EXCLUDED.format, animes.format), 
-            genres = COALESCE(EXCLUDED.genres, animes.genres), 
-            source = COALESCE(EXCLUDED.source, animes.source), 
-            studios_id = COALESCE(EXCLUDED.studios_id, animes.studios_id), 
-            studios_name = COALESCE(EXCLUDED.studios_name, animes.studios_name), 
-            studios_url = COALESCE(EXCLUDED.studios_url, animes.studios_url), 
-            tags_id = COALESCE(EXCLUDED.tags_id, animes.tags_id), 
-            tags_name = COALESCE(EXCLUDED.tags_name, animes.tags_name), 
-            trailer_id = COALESCE(EXCLUDED.trailer_id, animes.trailer_id), 
-            trailer_site = COALESCE(EXCLUDED.trailer_site, animes.trailer_site), 
-            trailer_thumbnail = COALESCE(EXCLUDED.trailer_thumbnail, animes.trailer_thumbnail), 
-            episodes = COALESCE(EXCLUDED.episodes, animes.episodes),
-            season = COALESCE(EXCLUDED.season, animes.season), 
-            season_year = COALESCE(EXCLUDED.season_year, animes.season_year), 
-            vintage_release_season = COALESCE(EXCLUDED.vintage_release_season, animes.vintage_release_season), 
-            vintage_release_year = COALESCE(EXCLUDED.vintage_release_year, animes.vintage_release_year);
-        "#,
+            ") AS c (artist_id, spotify_id, confidence, song_id)
+            WHERE NOT EXISTS (
+                SELECT 1 FROM spotify_artist_links l
+                WHERE l.artist_id = c.artist_id AND l.spotify_id = c.spotify_id
+            )
+            ON CONFLICT (spotify_id, artist_id) DO UPDATE
+            SET confidence = EXCLUDED.confidence, song_id = EXCLUDED.song_id
+            WHERE spotify_artist_link_candidates.confidence < EXCLUDED.confidence",
         );
-
         query_builder
             .build()
             .execute(&self.pool)
@@ -279,126 +452,38 @@ impl Database for DatabaseR {
             .unwrap()
             .rows_affected()
     }
+    async fn add_animes(&self, animes: Vec<DBAnime>) -> u64 {
+        let anime_ids = animes.iter().map(|a| a.ann_id).collect();
+        let mut tx = self.pool.begin().await.unwrap();
+        let count = upsert::upsert_animes(&mut tx, animes).await;
+        upsert::refresh_anisong_view_for(&mut tx, vec![], anime_ids, vec![]).await;
+        tx.commit().await.unwrap();
+        count.inserted + count.updated
+    }
     async fn add_artists(&self, artists: Vec<SimplifiedArtist>) -> u64 {
-        if artists.is_empty() {
-            return 0;
-        }
-        let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
-            "INSERT INTO artists (id, names, line_up_id, group_ids, member_ids) ",
-        );
-        query_builder.push_values(artists, |mut builder, artist| {
-            builder
-                .push_bind(artist.id)
-                .push_bind(artist.names)
-                .push_bind(artist.line_up_id)
-                .push_bind(artist.group_ids)
-                .push_bind(artist.member_ids);
-        });
-        query_builder.push(
-            r#" ON CONFLICT ( id ) DO UPDATE SET
-        names = array_unique(artists.names, EXCLUDED.names),
-        group_ids = array_unique(artists.group_ids, EXCLUDED.group_ids),
-        member_ids = array_unique(artists.group_ids, EXCLUDED.group_ids),
-        line_up_id = EXCLUDED.line_up_id
-        "#,
-        );
-        query_builder
-            .build()
-            .execute(&self.pool)
-            .await
-            .unwrap()
-            .rows_affected()
+        let artist_ids = artists.iter().map(|a| a.id).collect();
+        let mut tx = self.pool.begin().await.unwrap();
+        let count = upsert::upsert_artists(&mut tx, artists).await;
+        upsert::refresh_anisong_view_for(&mut tx, vec![], vec![], artist_ids).await;
+        tx.commit().await.unwrap();
+        count.inserted + count.updated
     }
     async fn add_songs(&self, songs: Vec<SimplifiedAnisongSong>) -> Vec<SongID> {
-        if songs.is_empty() {
-            return vec![];
-        }
-        // let mut song_set = HashSet::new();
-        // songs.retain(|a| song_set.insert((a.name.clone(), a.artists.clone())));
-        let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
-            r#"WITH data (temp_order, name, artist_name, composer_name, arranger_name, category, length, is_dub, hq, mq, audio, artists, composers, arrangers) AS ("#,
-        );
-        query_builder.push_values(songs.into_iter().enumerate(), |mut builder, song| {
-            builder
-                .push_bind(song.0 as i64)
-                .push_bind(song.1.name)
-                .push_bind(song.1.artist_name)
-                .push_bind(song.1.composer_name)
-                .push_bind(song.1.arranger_name)
-                .push_bind(song.1.category)
-                .push_bind(song.1.length)
-                .push_bind(song.1.is_dub)
-                .push_bind(song.1.hq)
-                .push_bind(song.1.mq)
-                .push_bind(song.1.audio)
-                .push_bind(
-                    song.1
-                        .artists
-                        .iter()
-                        .map(|a| a.id)
-                        .collect::<Vec<AnisongArtistID>>(),
-                )
-                .push_bind(
-                    song.1
-                        .composers
-                        .iter()
-                        .map(|a| a.id)
-                        .collect::<Vec<AnisongArtistID>>(),
-                )
-                .push_bind(
-                    song.1
-                        .arrangers
-                        .iter()
-                        .map(|a| a.id)
-                        .collect::<Vec<AnisongArtistID>>(),
-                );
-        });
-        query_builder.push(
-            r#") INSERT INTO songs (name, artist_name, composer_name, arranger_name, category, length, is_dub, hq, mq, audio, artists, composers, arrangers)
-                    SELECT name, artist_name, composer_name, arranger_name, category, length, is_dub, hq, mq, audio, artists, composers, arrangers FROM data
-                    ON CONFLICT (name, sort_int_array(artists)) DO UPDATE
-                        SET name = EXCLUDED.name
-                    RETURNING id;
-        "#);
-
-        let pairs: Vec<SongID> = query_builder
-            .build_query_as()
-            .fetch_all(&self.pool)
-            .await
-            .unwrap();
-        pairs
+        let mut tx = self.pool.begin().await.unwrap();
+        let song_ids = upsert::upsert_songs(&mut tx, songs).await.0;
+        upsert::refresh_anisong_view_for(&mut tx, song_ids.clone(), vec![], vec![]).await;
+        tx.commit().await.unwrap();
+        song_ids
     }
     async fn add_anisong_bind(&self, binds: Vec<DBAnisongBind>) -> u64 {
-        if binds.is_empty() {
-            return 0;
-        }
-        let mut query_builder: QueryBuilder<'_, Postgres> = QueryBuilder::new(
-            "INSERT INTO anime_song_links (song_id, anime_ann_id, song_ann_id, difficulty, song_index_type, song_index_number, is_rebroadcast) ",
-        );
-
-        query_builder.push_values(binds, |mut builder, bind| {
-            assert!(bind.song_id.is_some());
-            assert!(bind.anime_ann_id.is_some());
-
-            builder
-                .push_bind(bind.song_id)
-                .push_bind(bind.anime_ann_id)
-                .push_bind(bind.song_ann_id)
-                .push_bind(bind.difficulty)
-                .push_bind(bind.song_index.index_type)
-                .push_bind(bind.song_index.number)
-                .push_bind(bind.is_rebroadcast);
-        });
-
-        query_builder.push(" ON CONFLICT DO NOTHING");
-        query_builder
-            .build()
-            .execute(&self.pool)
-            .await
-            .unwrap()
-            .rows_affected()
+        let song_ids = binds.iter().filter_map(|b| b.song_id).collect();
+        let mut tx = self.pool.begin().await.unwrap();
+        let count = upsert::upsert_anisong_binds(&mut tx, binds).await;
+        upsert::refresh_anisong_view_for(&mut tx, song_ids, vec![], vec![]).await;
+        tx.commit().await.unwrap();
+        count.inserted + count.updated
     }
-    async fn add_from_anisongs(&self, anisongs: Vec<Anisong>, media: Vec<Media>) {
+    async fn add_from_anisongs(&self, anisongs: Vec<Anisong>, media: Vec<Media>) -> ImportSummary {
         let (mut anime, (bind, song)): (Vec<AnisongAnime>, (Vec<AnisongBind>, Vec<AnisongSong>)) =
             anisongs
                 .into_iter()
@@ -407,8 +492,10 @@ impl Database for DatabaseR {
 
         let mut anime_set = HashSet::new();
         anime.retain(|a| anime_set.insert(a.ann_id));
+        let anime_ids: Vec<AnnAnimeID> = anime.iter().map(|a| a.ann_id).collect();
 
         let (simplified_song, artists) = SimplifiedAnisongSong::decompose_all(song);
+        let artist_ids: Vec<AnisongArtistID> = artists.iter().map(|a| a.id).collect();
 
         let mut song_set = HashMap::new();
         let mut binds: Vec<Vec<AnisongBind>> = Vec::new();
@@ -418,14 +505,15 @@ impl Database for DatabaseR {
             .into_iter()
             .zip(bind.into_iter())
             .for_each(|esb| {
-                let k = (
-                    esb.0.name.clone(),
-                    esb.0
-                        .artists
-                        .iter()
-                        .map(|a| a.id)
-                        .collect::<Vec<AnisongArtistID>>(),
-                );
+                // Sorted to match the unique index on songs, one upsert can't touch a row twice
+                let mut artist_ids = esb
+                    .0
+                    .artists
+                    .iter()
+                    .map(|a| a.id)
+                    .collect::<Vec<AnisongArtistID>>();
+                artist_ids.sort();
+                let k = (esb.0.name.clone(), artist_ids);
                 match song_set.entry(k) {
                     std::collections::hash_map::Entry::Vacant(entry) => {
                         entry.insert(index);
@@ -441,10 +529,13 @@ impl Database for DatabaseR {
 
         let db_animes = DBAnime::combine(anime, media);
 
-        self.add_animes(db_animes).await;
-        let bind_data = self.add_songs(songs).await;
+        let mut tx = self.pool.begin().await.unwrap();
+
+        let animes = upsert::upsert_animes(&mut tx, db_animes).await;
+        let (bind_data, songs) = upsert::upsert_songs(&mut tx, songs).await;
         assert_eq!(bind_data.len(), binds.len());
 
+        let song_ids = bind_data.clone();
         let mut binds2 = Vec::new();
         bind_data.into_iter().zip(binds.into_iter()).for_each(|a| {
             let (id, anisong_binds) = a;
@@ -459,8 +550,24 @@ impl Database for DatabaseR {
                 })
             })
         });
-        self.add_anisong_bind(binds2).await;
-        self.add_artists(artists).await;
+        let anime_song_links = upsert::upsert_anisong_binds(&mut tx, binds2).await;
+        let artists = upsert::upsert_artists(&mut tx, artists).await;
+        upsert::refresh_anisong_view_for(&mut tx, song_ids, anime_ids, artist_ids).await;
+
+        tx.commit().await.unwrap();
+
+        ImportSummary {
+            animes,
+            songs,
+            artists,
+            anime_song_links,
+        }
+    }
+    async fn refresh_anisong_view(&self) -> u64 {
+        sqlx::query_scalar::<Postgres, i64>("SELECT refresh_anisong_view_all()")
+            .fetch_one(&self.pool)
+            .await
+            .unwrap() as u64
     }
     async fn add_report(&self, report: Report) {
         sqlx::query::<Postgres>(
@@ -482,12 +589,13 @@ impl Database for DatabaseR {
         artist_names: Vec<String>,
         whole_word_match: bool,
         case_sensitive: bool,
-    ) -> Vec<DBAnisong> {
+        page: Option<Page>,
+    ) -> Paged<DBAnisong> {
         let song_regex = regex::create_regex(&song_name, whole_word_match);
         let artist_regex =
             regex::create_artist_regex(artist_names.iter().collect(), whole_word_match);
         let regex_type = if case_sensitive { "~" } else { "~*" };
-        sqlx::query_as::<Postgres, DBAnisong>(&format!(
+        let rows = sqlx::query::<Postgres>(&format!(
            " WITH related_artist_ids AS (
                 SELECT ARRAY_AGG(DISTINCT ids) AS ids
                     FROM (
@@ -500,27 +608,42 @@ impl Database for DatabaseR {
                                 LIMIT 1  -- Only need to find at least one match
                             )
                     ) subq
+                ),
+                matches AS (
+                    SELECT s.* FROM anisong_view s, related_artist_ids
+                    WHERE 
+                        s.artist_ids && related_artist_ids.ids OR 
+                        s.composer_ids && related_artist_ids.ids OR
+                        s.song_name {0} $2
                 )
-                SELECT * FROM anisong_view s, related_artist_ids
-                WHERE 
-                    s.artist_ids && related_artist_ids.ids OR 
-                    s.composer_ids && related_artist_ids.ids OR
-                    s.song_name {0} $2;", regex_type
+                SELECT p.*, c.total_count
+                FROM (SELECT COUNT(*) AS total_count FROM matches) c
+                LEFT JOIN (
+                    SELECT * FROM matches
+                    ORDER BY song_id, song_ann_id
+                    LIMIT $3 OFFSET $4
+                ) p ON true
+                ORDER BY p.song_id, p.song_ann_id;", regex_type
         ))
         .bind(artist_regex)
         .bind(song_regex)
+        .bind(page.map(|p| p.limit))
+        .bind(page.map(|p| p.offset))
         .fetch_all(&self.pool)
         .await
-        .unwrap()
+        .unwrap();
+        paged_anisongs(rows)
     }
     async fn get_anisongs_by_ani_artist_ids(
         &self,
         artist_ids: Vec<AnisongArtistID>,
-    ) -> Vec<DBAnisong> {
+        exclude_song_ids: Vec<SongID>,
+        page: Option<Page>,
+    ) -> Paged<DBAnisong> {
         if artist_ids.is_empty() {
-            return vec![];
+            return Paged::default();
         }
-        sqlx::query_as::<Postgres, DBAnisong>(
+        let rows = sqlx::query::<Postgres>(
             r#"
             WITH related_artist_ids AS (
                 -- Get all related artist IDs including groups and members
@@ -530,20 +653,110 @@ impl Database for DatabaseR {
                     FROM artists a
                     WHERE a.id = ANY($1)
                 ) subq
+            ),
+            matches AS (
+                SELECT DISTINCT s.*
+                FROM related_artist_ids, anisong_view s
+                WHERE 
+                    (s.artist_ids && related_artist_ids.ids OR 
+                    s.composer_ids && related_artist_ids.ids)
+                    AND s.song_id <> ALL($2)
             )
-            SELECT DISTINCT *
-            FROM related_artist_ids, anisong_view s
-            WHERE 
-                s.artist_ids && related_artist_ids.ids OR 
-                s.composer_ids && related_artist_ids.ids
-            ORDER BY s.song_id;
+            SELECT p.*, c.total_count
+            FROM (SELECT COUNT(*) AS total_count FROM matches) c
+            LEFT JOIN (
+                SELECT * FROM matches
+                ORDER BY song_id, song_ann_id
+                LIMIT $3 OFFSET $4
+            ) p ON true
+            ORDER BY p.song_id, p.song_ann_id;
             "#,
         )
         .bind(artist_ids)
+        .bind(exclude_song_ids)
+        .bind(page.map(|p| p.limit))
+        .bind(page.map(|p| p.offset))
+        .fetch_all(&self.pool)
+        .await
+        .unwrap();
+        paged_anisongs(rows)
+    }
+
+    async fn get_anime(&self, lookup: AnimeLookup) -> Option<DBAnime> {
+        match lookup {
+            AnimeLookup::AnnId(id) => {
+                sqlx::query_as::<Postgres, DBAnime>(
+                    "SELECT * FROM anime_view WHERE anime_ann_id = $1",
+                )
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await
+            }
+            AnimeLookup::AnilistId(id) => {
+                sqlx::query_as::<Postgres, DBAnime>(
+                    "SELECT * FROM anime_view WHERE anilist_id = $1 ORDER BY anime_ann_id LIMIT 1",
+                )
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await
+            }
+        }
+        .unwrap()
+    }
+    async fn get_songs_for_anime(&self, ann_id: AnnAnimeID) -> Vec<DBAnisong> {
+        sqlx::query_as::<Postgres, DBAnisong>(
+            r#"
+            SELECT * FROM anisong_view
+            WHERE anime_ann_id = $1
+            ORDER BY
+                CASE song_index_type
+                    WHEN 'opening' THEN 0
+                    WHEN 'ending' THEN 1
+                    ELSE 2
+                END,
+                song_index_number,
+                song_ann_id;
+            "#,
+        )
+        .bind(ann_id)
         .fetch_all(&self.pool)
         .await
         .unwrap()
     }
+    async fn search_anime(&self, name: String, page: Page) -> Paged<DBAnime> {
+        let name_regex = regex::create_regex(&name, false);
+        let rows = sqlx::query::<Postgres>(
+            r#"
+            WITH matches AS (
+                SELECT *
+                FROM anime_view a
+                WHERE 
+                    a.anime_eng_name ~* $1 OR
+                    a.anime_jpn_name ~* $1 OR
+                    EXISTS (
+                        SELECT 1
+                        FROM unnest(a.anime_alt_names) AS alt_name
+                        WHERE alt_name ~* $1
+                    )
+            )
+            SELECT p.*, c.total_count
+            FROM (SELECT COUNT(*) AS total_count FROM matches) c
+            LEFT JOIN (
+                SELECT * FROM matches
+                ORDER BY anime_eng_name, anime_ann_id
+                LIMIT $2 OFFSET $3
+            ) p ON true
+            ORDER BY p.anime_eng_name, p.anime_ann_id;
+            "#,
+        )
+        .bind(name_regex)
+        .bind(page.limit)
+        .bind(page.offset)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap();
+        paged(rows, "anime_ann_id")
+    }
 
     async fn get_user(&self, user_id: SpotifyUserID) -> Option<DBUser> {
         sqlx::query_as::<Postgres, DBUser>(
@@ -577,6 +790,488 @@ impl Database for DatabaseR {
             }
         }
     }
+    async fn add_listen(&self, user_id: SpotifyUserID, listen: Listen) {
+        sqlx::query::<Postgres>(
+            r#"
+            INSERT INTO listens (user_id, track_id, track_name, artist_names, hit, certainty, song_id, anime_ann_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        )
+        .bind(user_id)
+        .bind(listen.track_id)
+        .bind(listen.track_name)
+        .bind(listen.artist_names)
+        .bind(listen.hit)
+        .bind(listen.certainty)
+        .bind(listen.song_id)
+        .bind(listen.anime_ann_id)
+        .execute(&self.pool)
+        .await
+        .unwrap();
+    }
+    async fn get_listens(
+        &self,
+        user_id: SpotifyUserID,
+        hits_only: bool,
+        page: Page,
+    ) -> Paged<DBListen> {
+        let rows = sqlx::query::<Postgres>(
+            r#"
+            WITH matches AS (
+                SELECT * FROM listens l
+                WHERE l.user_id = $1 AND (NOT $2 OR (l.hit AND l.anime_ann_id IS NOT NULL))
+            )
+            SELECT p.*, c.total_count
+            FROM (SELECT COUNT(*) AS total_count FROM matches) c
+            LEFT JOIN (
+                SELECT l.track_id, l.track_name, l.artist_names, l.hit, l.certainty, l.song_id,
+                    l.anime_ann_id, a.eng_name AS anime_eng_name, a.jpn_name AS anime_jpn_name,
+                    l.listened_at, l.listen_id
+                FROM matches l
+                LEFT JOIN animes a ON a.ann_id = l.anime_ann_id
+                ORDER BY l.listened_at DESC, l.listen_id DESC
+                LIMIT $3 OFFSET $4
+            ) p ON true
+            ORDER BY p.listened_at DESC, p.listen_id DESC
+        "#,
+        )
+        .bind(user_id)
+        .bind(hits_only)
+        .bind(page.limit)
+        .bind(page.offset)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap();
+        paged(rows, "track_id")
+    }
+    async fn get_user_stats(
+        &self,
+        user_id: SpotifyUserID,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> UserStats {
+        let (listens, anime_listens): (i64, i64) = sqlx::query_as(&format!(
+            r#"{USER_LISTENS}
+            SELECT COUNT(*), COUNT(*) FILTER (WHERE hit AND anime_ann_id IS NOT NULL) FROM l
+        "#
+        ))
+        .bind(&user_id)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap();
+
+        let top_anime = self
+            .stat_counts(
+                r#"
+            SELECT a.ann_id AS id, a.eng_name AS name, COUNT(*) AS count
+            FROM l JOIN animes a ON a.ann_id = l.anime_ann_id
+            WHERE l.hit
+            GROUP BY a.ann_id, a.eng_name
+            ORDER BY count DESC, name
+            LIMIT $3
+        "#,
+                &user_id,
+                since,
+                limit,
+            )
+            .await;
+        let top_artists = self
+            .stat_counts(
+                r#"
+            SELECT ar.id, ar.names[1] AS name, COUNT(*) AS count
+            FROM l
+            JOIN songs s ON s.id = l.song_id
+            CROSS JOIN LATERAL unnest(s.artists) AS artist_id
+            JOIN artists ar ON ar.id = artist_id
+            WHERE l.hit
+            GROUP BY ar.id, ar.names[1]
+            ORDER BY count DESC, name
+            LIMIT $3
+        "#,
+                &user_id,
+                since,
+                limit,
+            )
+            .await;
+        let genres = self
+            .stat_counts(
+                r#"
+            SELECT NULL::INTEGER AS id, genre AS name, COUNT(*) AS count
+            FROM l
+            JOIN animes a ON a.ann_id = l.anime_ann_id
+            CROSS JOIN LATERAL unnest(a.genres) AS genre
+            WHERE l.hit
+            GROUP BY genre
+            ORDER BY count DESC, name
+            LIMIT $3
+        "#,
+                &user_id,
+                since,
+                limit,
+            )
+            .await;
+        let studios = self
+            .stat_counts(
+                r#"
+            SELECT studio.id, studio.name, COUNT(*) AS count
+            FROM l
+            JOIN animes a ON a.ann_id = l.anime_ann_id
+            CROSS JOIN LATERAL unnest(a.studios_id, a.studios_name) AS studio(id, name)
+            WHERE l.hit
+            GROUP BY studio.id, studio.name
+            ORDER BY count DESC, name
+            LIMIT $3
+        "#,
+                &user_id,
+                since,
+                limit,
+            )
+            .await;
+
+        // A song can be bound to an anime more than once, the listen counts toward the first type
+        let mut song_types = sqlx::query_as::<Postgres, SongTypeStat>(&format!(
+            r#"{USER_LISTENS}
+            SELECT t.song_index_type AS song_type, COUNT(*) AS count
+            FROM l
+            CROSS JOIN LATERAL (
+                SELECT asl.song_index_type FROM anime_song_links asl
+                WHERE asl.song_id = l.song_id AND asl.anime_ann_id = l.anime_ann_id
+                ORDER BY asl.song_index_type
+                LIMIT 1
+            ) t
+            WHERE l.hit
+            GROUP BY t.song_index_type
+            ORDER BY t.song_index_type
+        "#
+        ))
+        .bind(&user_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap();
+        let typed: i64 = song_types.iter().map(|t| t.count).sum();
+        song_types
+            .iter_mut()
+            .for_each(|t| t.ratio = t.count as f64 / typed as f64);
+
+        let seasons = sqlx::query_as::<Postgres, SeasonStat>(&format!(
+            r#"{USER_LISTENS}
+            SELECT
+                COALESCE(a.vintage_release_season, a.season) AS season,
+                COALESCE(a.vintage_release_year, a.season_year) AS year,
+                COUNT(*) AS count
+            FROM l JOIN animes a ON a.ann_id = l.anime_ann_id
+            WHERE l.hit
+                AND COALESCE(a.vintage_release_season, a.season) IS NOT NULL
+                AND COALESCE(a.vintage_release_year, a.season_year) IS NOT NULL
+            GROUP BY 1, 2
+            ORDER BY count DESC, year DESC
+            LIMIT $3
+        "#
+        ))
+        .bind(&user_id)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap();
+
+        UserStats {
+            listens,
+            anime_listens,
+            top_anime,
+            top_artists,
+            genres,
+            studios,
+            song_types,
+            seasons,
+        }
+    }
+    async fn get_playlist_songs(&self, source: PlaylistSource) -> Vec<PlaylistSong> {
+        let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            r#"
+            WITH picked AS (
+                SELECT asl.song_id,
+                    MIN(a.vintage_release_year) AS year,
+                    MIN(a.ann_id) AS ann_id,
+                    MIN(asl.song_index_type) AS song_index_type,
+                    MIN(asl.song_index_number) AS song_index_number
+                FROM anime_song_links asl
+                JOIN animes a ON a.ann_id = asl.anime_ann_id
+                JOIN songs s ON s.id = asl.song_id
+                WHERE "#,
+        );
+        match source {
+            PlaylistSource::Anime(ann_id) => {
+                query_builder.push("a.ann_id = ").push_bind(ann_id);
+            }
+            PlaylistSource::Season(release) => {
+                query_builder
+                    .push("a.vintage_release_season = ")
+                    .push_bind(release.season)
+                    .push(" AND a.vintage_release_year = ")
+                    .push_bind(release.year);
+            }
+            PlaylistSource::Artist(artist_id) => {
+                query_builder.push_bind(artist_id).push(" = ANY(s.artists)");
+            }
+            PlaylistSource::Tag(tag_id) => {
+                query_builder.push_bind(tag_id).push(" = ANY(a.tags_id)");
+            }
+        }
+        query_builder.push(
+            r#"
+                GROUP BY asl.song_id
+            )
+            SELECT s.id AS song_id, s.name AS song_name, s.artist_name, link.spotify_id
+            FROM picked p
+            JOIN songs s ON s.id = p.song_id
+            LEFT JOIN LATERAL (
+                SELECT ssl.spotify_id FROM spotify_song_links ssl
+                WHERE ssl.song_id = s.id
+                ORDER BY ssl.spotify_id
+                LIMIT 1
+            ) link ON TRUE
+            ORDER BY p.year NULLS LAST, p.ann_id, p.song_index_type, p.song_index_number, s.id
+        "#,
+        );
+        query_builder
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+    async fn get_library_scan(&self, user_id: SpotifyUserID) -> Option<LibraryScan> {
+        sqlx::query_as::<Postgres, LibraryScan>("SELECT * FROM library_scans WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap()
+    }
+    async fn start_library_scan(&self, user_id: SpotifyUserID, auto_bind: bool) -> LibraryScan {
+        let mut tx = self.pool.begin().await.unwrap();
+        sqlx::query::<Postgres>("DELETE FROM library_scan_hits WHERE user_id = $1")
+            .bind(&user_id)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        let scan = sqlx::query_as::<Postgres, LibraryScan>(
+            r#"
+            INSERT INTO library_scans (user_id, auto_bind) VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE SET
+                auto_bind = EXCLUDED.auto_bind,
+                saved_offset = 0,
+                playlist_offset = 0,
+                track_offset = 0,
+                tracks_scanned = 0,
+                finished = FALSE,
+                started_at = NOW(),
+                updated_at = NOW()
+            RETURNING *
+        "#,
+        )
+        .bind(user_id)
+        .bind(auto_bind)
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        scan
+    }
+    async fn save_library_scan(&self, scan: LibraryScan, hits: Vec<ScanHit>) {
+        let mut tx = self.pool.begin().await.unwrap();
+        if !hits.is_empty() {
+            let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+                "INSERT INTO library_scan_hits (user_id, track_id, track_name, artist_names, song_id, anime_ann_id, certainty, found_in) ",
+            );
+            query_builder.push_values(hits, |mut builder, hit| {
+                builder
+                    .push_bind(&scan.user_id)
+                    .push_bind(hit.track_id)
+                    .push_bind(hit.track_name)
+                    .push_bind(hit.artist_names)
+                    .push_bind(hit.song_id)
+                    .push_bind(hit.anime_ann_id)
+                    .push_bind(hit.certainty)
+                    .push_bind(hit.found_in);
+            });
+            // The same track is often saved and in playlists, keep where it was first found
+            query_builder.push(" ON CONFLICT (user_id, track_id) DO NOTHING");
+            query_builder.build().execute(&mut *tx).await.unwrap();
+        }
+        sqlx::query::<Postgres>(
+            r#"
+            UPDATE library_scans SET
+                auto_bind = $2,
+                saved_offset = $3,
+                playlist_offset = $4,
+                track_offset = $5,
+                tracks_scanned = $6,
+                finished = $7,
+                updated_at = NOW()
+            WHERE user_id = $1
+        "#,
+        )
+        .bind(&scan.user_id)
+        .bind(scan.auto_bind)
+        .bind(scan.saved_offset)
+        .bind(scan.playlist_offset)
+        .bind(scan.track_offset)
+        .bind(scan.tracks_scanned)
+        .bind(scan.finished)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+    }
+    async fn get_library_scan_hits(&self, user_id: SpotifyUserID, page: Page) -> Paged<DBScanHit> {
+        let rows = sqlx::query::<Postgres>(
+            r#"
+            SELECT p.*, c.total_count
+            FROM (SELECT COUNT(*) AS total_count FROM library_scan_hits WHERE user_id = $1) c
+            LEFT JOIN (
+                SELECT h.track_id, h.track_name, h.artist_names, h.song_id, h.anime_ann_id,
+                    a.eng_name AS anime_eng_name, a.jpn_name AS anime_jpn_name, h.certainty,
+                    h.found_in
+                FROM library_scan_hits h
+                LEFT JOIN animes a ON a.ann_id = h.anime_ann_id
+                WHERE h.user_id = $1
+                ORDER BY h.certainty DESC, a.eng_name, h.track_name
+                LIMIT $2 OFFSET $3
+            ) p ON true
+            ORDER BY p.certainty DESC, p.anime_eng_name, p.track_name
+        "#,
+        )
+        .bind(user_id)
+        .bind(page.limit)
+        .bind(page.offset)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap();
+        paged(rows, "track_id")
+    }
+    async fn get_import_checkpoint(&self, name: String) -> Option<Release> {
+        sqlx::query::<Postgres>(
+            "SELECT season, year FROM season_import_checkpoints WHERE name = $1",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap()
+        .map(|r| Release {
+            season: r.get("season"),
+            year: r.get("year"),
+        })
+    }
+    async fn export_rows(
+        &self,
+        table: SnapshotTable,
+        after: Option<serde_json::Value>,
+        limit: i64,
+    ) -> Vec<serde_json::Value> {
+        sqlx::query_scalar::<Postgres, serde_json::Value>(&format!(
+            "SELECT to_jsonb(t) FROM {0} t
+            WHERE $1::jsonb IS NULL OR ({1}) > (SELECT {1} FROM jsonb_populate_record(NULL::{0}, $1))
+            ORDER BY {1} LIMIT $2",
+            table.name(),
+            table.order_by()
+        ))
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap()
+    }
+    async fn export_csv<W: std::io::Write + Send>(
+        &self,
+        table: SnapshotTable,
+        out: &mut W,
+    ) -> std::io::Result<()> {
+        let mut conn = self.pool.acquire().await.map_err(std::io::Error::other)?;
+        let mut stream = conn
+            .copy_out_raw(&format!(
+                "COPY (SELECT * FROM {} ORDER BY {}) TO STDOUT WITH (FORMAT csv, HEADER)",
+                table.name(),
+                table.order_by()
+            ))
+            .await
+            .map_err(std::io::Error::other)?;
+        while let Some(bytes) = stream.next().await {
+            out.write_all(&bytes.map_err(std::io::Error::other)?)?;
+        }
+        Ok(())
+    }
+    async fn restore_snapshot(
+        &self,
+        tables: Vec<(SnapshotTable, SnapshotSource)>,
+    ) -> Result<Vec<(SnapshotTable, u64)>, SnapshotError> {
+        // Dropping the transaction on an error rolls back the tables restored before it.
+        let mut tx = self.pool.begin().await?;
+        let inserted = snapshot::restore_tables(&mut tx, tables).await?;
+        tx.commit().await?;
+        Ok(inserted)
+    }
+    async fn save_season_import(&self, import: SeasonImport) {
+        let mut tx = self.pool.begin().await.unwrap();
+        sqlx::query::<Postgres>(
+            r#"
+            INSERT INTO season_imports (name, season, year, attempts, songs, summary, error, finished_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        )
+        .bind(&import.name)
+        .bind(import.season)
+        .bind(import.year)
+        .bind(import.attempts)
+        .bind(import.songs)
+        .bind(sqlx::types::Json(import.summary))
+        .bind(&import.error)
+        .bind(import.finished_at)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        if import.error.is_none() {
+            sqlx::query::<Postgres>(
+                r#"
+                INSERT INTO season_import_checkpoints (name, season, year) VALUES ($1, $2, $3)
+                ON CONFLICT (name) DO UPDATE SET
+                    season = EXCLUDED.season,
+                    year = EXCLUDED.year,
+                    updated_at = NOW()
+            "#,
+            )
+            .bind(&import.name)
+            .bind(import.season)
+            .bind(import.year)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        }
+        tx.commit().await.unwrap();
+    }
+}
+
+/// Paged queries left join the page onto the count of all matches, so a page past the end still
+/// returns one row carrying the total. Its columns are NULL, `key` is never NULL in a real row.
+fn paged<T>(rows: Vec<PgRow>, key: &str) -> Paged<T>
+where
+    T: for<'r> FromRow<'r, PgRow>,
+{
+    let total = rows
+        .first()
+        .map(|r| r.get::<i64, _>("total_count"))
+        .unwrap_or(0);
+    let items = rows
+        .iter()
+        .filter(|r| !r.try_get_raw(key).unwrap().is_null())
+        .map(|r| T::from_row(r).unwrap())
+        .collect();
+    Paged { items, total }
+}
+
+fn paged_anisongs(rows: Vec<PgRow>) -> Paged<DBAnisong> {
+    paged(rows, "song_ann_id")
 }
 
 const ANI_SONGS_FROM_SPOTIFY_SONG: &str = r#"
@@ -598,9 +1293,8 @@ related_artist_ids AS (
         WHERE 
             a.id = ANY(sa.artists || sa.composers)
     ) subq
-)
-SELECT *
-FROM (
+),
+matches AS (
     SELECT DISTINCT 
            s.*,
            CASE 
@@ -611,8 +1305,15 @@ FROM (
     WHERE 
         s.artist_ids && related_artist_ids.ids OR 
         s.composer_ids && related_artist_ids.ids
-) sub
-ORDER BY order_priority;
+)
+SELECT p.*, c.total_count
+FROM (SELECT COUNT(*) AS total_count FROM matches) c
+LEFT JOIN (
+    SELECT * FROM matches
+    ORDER BY order_priority, song_id, song_ann_id
+    LIMIT $2 OFFSET $3
+) p ON true
+ORDER BY p.order_priority, p.song_id, p.song_ann_id;
 "#;
 
 const ANI_SONGS_FROM_SPOTIFY_ARTISTS: &str = r#"
@@ -630,20 +1331,33 @@ related_artist_ids AS (
         FROM artists a
         WHERE a.id IN (SELECT artist_id FROM artist_link)
     ) subq
+),
+matches AS (
+    SELECT DISTINCT s.*
+    FROM related_artist_ids, anisong_view s
+    WHERE 
+        s.artist_ids && related_artist_ids.ids OR 
+        s.composer_ids && related_artist_ids.ids
 )
-SELECT DISTINCT *
-FROM related_artist_ids, anisong_view s
-WHERE 
-    s.artist_ids && related_artist_ids.ids OR 
-    s.composer_ids && related_artist_ids.ids
-ORDER BY s.song_id;
+SELECT p.*, c.total_count
+FROM (SELECT COUNT(*) AS total_count FROM matches) c
+LEFT JOIN (
+    SELECT * FROM matches
+    ORDER BY song_id, song_ann_id
+    LIMIT $2 OFFSET $3
+) p ON true
+ORDER BY p.song_id, p.song_ann_id;
 "#;
 
 #[cfg(test)]
 mod tests {
-    use crate::{Database, DatabaseR};
-    use anisong_api::models::AnisongArtistID;
+    use crate::{
+        Database, DatabaseR,
+        models::{AnimeLookup, Page},
+    };
+    use anisong_api::models::{Anisong, AnisongArtistID, SongAnnId};
     use dotenvy;
+    use sqlx::Postgres;
     use what_anime_shared::{SpotifyArtistID, SpotifyTrackID};
 
     #[tokio::test]
@@ -657,24 +1371,39 @@ mod tests {
             SpotifyArtistID("1tofuk7dTZwb6ZKsr7XRKB".to_string()),
             SpotifyArtistID("3D73KNJRMbV45N59E8IN0F".to_string()),
         ];
-        let a = db.get_anisongs_by_artist_ids(artist_ids).await;
+        let a = db.get_anisongs_by_artist_ids(artist_ids, None).await.items;
         let b = db.get_artists(artists).await;
         let song = "idol".to_string();
         let artists = vec!["LiSA".to_string(), "Sumire Uesaka".to_string()];
         let c = db
-            .full_search(song.clone(), artists.clone(), false, false)
-            .await;
+            .full_search(song.clone(), artists.clone(), false, false, None)
+            .await
+            .items;
         let d = db
-            .full_search(song.clone(), artists.clone(), true, false)
-            .await;
+            .full_search(song.clone(), artists.clone(), true, false, None)
+            .await
+            .items;
         let e = db
-            .full_search(song.clone(), artists.clone(), false, true)
-            .await;
+            .full_search(song.clone(), artists.clone(), false, true, None)
+            .await
+            .items;
         let f = db
-            .full_search(song.clone(), artists.clone(), true, true)
-            .await;
+            .full_search(song.clone(), artists.clone(), true, true, None)
+            .await
+            .items;
         let g = db
-            .get_anisongs_by_song_id(SpotifyTrackID("4svcLG3SimzCbxH0RT7Omb".to_string()))
+            .get_anisongs_by_song_id(SpotifyTrackID("4svcLG3SimzCbxH0RT7Omb".to_string()), None)
+            .await
+            .items;
+        let h = db
+            .get_anisongs_by_ani_artist_ids(
+                vec![AnisongArtistID(1)],
+                vec![],
+                Some(Page {
+                    limit: 2,
+                    offset: 0,
+                }),
+            )
             .await;
         assert!(!a.is_empty());
         assert!(!b.is_empty());
@@ -683,7 +1412,63 @@ mod tests {
         assert!(!e.is_empty());
         assert!(!f.is_empty());
         assert!(!g.is_empty());
+        assert!(h.items.len() <= 2);
+        assert!(h.total >= h.items.len() as i64);
+
+        let animes = db
+            .search_anime(
+                "Bocchi the Rock".to_string(),
+                Page {
+                    limit: 5,
+                    offset: 0,
+                },
+            )
+            .await;
+        assert!(!animes.items.is_empty());
+        let ann_id = animes.items[0].ann_id;
+        assert!(db.get_anime(AnimeLookup::AnnId(ann_id)).await.is_some());
+        assert!(!db.get_songs_for_anime(ann_id).await.is_empty());
         eprintln!("{:#?}", a);
         // assert!(false);
     }
+
+    #[tokio::test]
+    async fn test_song_without_artists() {
+        dotenvy::from_path("../../dev.env").expect("Failed to load env file");
+
+        let db = DatabaseR::new(1).await;
+        let mut anisong: Anisong = serde_json::from_str::<Vec<Anisong>>(include_str!(
+            "../../anisong_api/src/testParse2.json"
+        ))
+        .unwrap()
+        .remove(0);
+        // Ids and names that no real record uses, so the test doesn't touch imported data.
+        anisong.song.name = "test_song_without_artists".to_string();
+        anisong.song.artists = vec![];
+        anisong.anisong_bind.song_ann_id = SongAnnId(-1);
+
+        db.add_from_anisongs(vec![anisong.clone()], vec![]).await;
+        let again = db.add_from_anisongs(vec![anisong], vec![]).await;
+        let copies =
+            sqlx::query_scalar::<Postgres, i64>("SELECT COUNT(*) FROM songs WHERE name = $1")
+                .bind("test_song_without_artists")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+
+        sqlx::query::<Postgres>("DELETE FROM anime_song_links WHERE song_ann_id = -1")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        sqlx::query::<Postgres>("DELETE FROM songs WHERE name = 'test_song_without_artists'")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        sqlx::query::<Postgres>("DELETE FROM anisong_view WHERE song_ann_id = -1")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(copies, 1);
+        assert_eq!(again.songs.inserted, 0);
+    }
 }
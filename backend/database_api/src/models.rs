@@ -10,7 +10,8 @@ use sqlx::{
 };
 
 use what_anime_shared::{
-    ImageURL, ReleaseSeason, SongID, SpotifyTrackID, SpotifyUser, SpotifyUserID,
+    AnilistAnimeID, ImageURL, ReleaseSeason, SongID, SpotifyArtistID, SpotifyTrackID, SpotifyUser,
+    SpotifyUserID,
 };
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DBAnime {
@@ -439,6 +440,17 @@ pub struct SimplifiedArtist {
     pub member_ids: Vec<AnisongArtistID>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DBArtist {
+    pub artist: SimplifiedArtist,
+    pub groups: Vec<SimplifiedArtist>,
+    pub members: Vec<SimplifiedArtist>,
+    pub spotify_ids: Vec<SpotifyArtistID>,
+    pub performed: Vec<DBAnisong>,
+    pub composed: Vec<DBAnisong>,
+    pub arranged: Vec<DBAnisong>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
 pub struct DBAnisongBind {
     pub song_id: Option<SongID>,
@@ -459,3 +471,250 @@ pub struct DBUser {
     pub binds: i32,
     pub flags: i64,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimeLookup {
+    AnnId(AnnAnimeID),
+    AnilistId(AnilistAnimeID),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Page {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Paged<T> {
+    pub items: Vec<T>,
+    /// Number of distinct matches across all pages, also given for a page past the end.
+    pub total: i64,
+}
+
+impl<T> Default for Paged<T> {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            total: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpsertCount {
+    pub inserted: u64,
+    pub updated: u64,
+    pub unchanged: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct ImportSummary {
+    pub animes: UpsertCount,
+    pub songs: UpsertCount,
+    pub artists: UpsertCount,
+    pub anime_song_links: UpsertCount,
+}
+
+impl std::ops::AddAssign for UpsertCount {
+    fn add_assign(&mut self, other: Self) {
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+    }
+}
+
+impl std::ops::AddAssign for ImportSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.animes += other.animes;
+        self.songs += other.songs;
+        self.artists += other.artists;
+        self.anime_song_links += other.anime_song_links;
+    }
+}
+
+/// A track a user played through `/update`, with what it was matched to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Listen {
+    pub track_id: SpotifyTrackID,
+    pub track_name: String,
+    pub artist_names: Vec<String>,
+    pub hit: bool,
+    pub certainty: Option<i32>,
+    pub song_id: Option<SongID>,
+    pub anime_ann_id: Option<AnnAnimeID>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct DBListen {
+    pub track_id: SpotifyTrackID,
+    pub track_name: String,
+    pub artist_names: Vec<String>,
+    pub hit: bool,
+    pub certainty: Option<i32>,
+    pub song_id: Option<SongID>,
+    pub anime_ann_id: Option<AnnAnimeID>,
+    pub anime_eng_name: Option<String>,
+    pub anime_jpn_name: Option<String>,
+    pub listened_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct StatCount {
+    pub id: Option<i32>,
+    pub name: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct SongTypeStat {
+    pub song_type: SongIndexType,
+    pub count: i64,
+    /// Share of the anime hits in the window, between 0 and 1.
+    #[sqlx(default)]
+    pub ratio: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct SeasonStat {
+    pub season: ReleaseSeason,
+    pub year: i32,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UserStats {
+    pub listens: i64,
+    pub anime_listens: i64,
+    pub top_anime: Vec<StatCount>,
+    pub top_artists: Vec<StatCount>,
+    pub genres: Vec<StatCount>,
+    pub studios: Vec<StatCount>,
+    pub song_types: Vec<SongTypeStat>,
+    pub seasons: Vec<SeasonStat>,
+}
+
+/// What a generated playlist is built from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistSource {
+    Anime(AnnAnimeID),
+    Season(Release),
+    Artist(AnisongArtistID),
+    Tag(TagID),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct PlaylistSong {
+    pub song_id: SongID,
+    pub song_name: String,
+    pub artist_name: String,
+    /// `None` when no Spotify track has been bound to the song yet.
+    pub spotify_id: Option<SpotifyTrackID>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct LibraryScan {
+    pub user_id: SpotifyUserID,
+    pub auto_bind: bool,
+    /// `None` once every saved track has been scanned.
+    pub saved_offset: Option<i32>,
+    /// Index of the playlist being scanned and the offset within it.
+    pub playlist_offset: i32,
+    pub track_offset: i32,
+    pub tracks_scanned: i32,
+    pub finished: bool,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// How one season of a named import went, `error` is set when every attempt failed.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct SeasonImport {
+    pub name: String,
+    pub season: ReleaseSeason,
+    pub year: i32,
+    pub attempts: i32,
+    pub songs: i32,
+    #[sqlx(json)]
+    pub summary: ImportSummary,
+    pub error: Option<String>,
+    pub finished_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Tables included in a snapshot, `ALL` is in the order they have to be restored in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotTable {
+    Animes,
+    Artists,
+    Songs,
+    AnimeSongLinks,
+    SpotifySongLinks,
+    SpotifyArtistLinks,
+}
+
+impl SnapshotTable {
+    pub const ALL: [Self; 6] = [
+        Self::Animes,
+        Self::Artists,
+        Self::Songs,
+        Self::AnimeSongLinks,
+        Self::SpotifySongLinks,
+        Self::SpotifyArtistLinks,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Animes => "animes",
+            Self::Artists => "artists",
+            Self::Songs => "songs",
+            Self::AnimeSongLinks => "anime_song_links",
+            Self::SpotifySongLinks => "spotify_song_links",
+            Self::SpotifyArtistLinks => "spotify_artist_links",
+        }
+    }
+    /// Primary key, so paged exports are stable.
+    pub fn order_by(&self) -> &'static str {
+        match self {
+            Self::Animes => "ann_id",
+            Self::Artists => "id",
+            Self::Songs => "id",
+            Self::AnimeSongLinks => "song_ann_id",
+            Self::SpotifySongLinks => "spotify_id, song_id",
+            Self::SpotifyArtistLinks => "spotify_id, artist_id",
+        }
+    }
+}
+
+/// An artist link that wasn't confident enough to bind, kept for review.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArtistLinkCandidate {
+    pub artist_id: AnisongArtistID,
+    pub spotify_id: SpotifyArtistID,
+    pub confidence: f32,
+    pub song_id: SongID,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScanHit {
+    pub track_id: SpotifyTrackID,
+    pub track_name: String,
+    pub artist_names: Vec<String>,
+    pub song_id: SongID,
+    pub anime_ann_id: AnnAnimeID,
+    pub certainty: i32,
+    pub found_in: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct DBScanHit {
+    pub track_id: SpotifyTrackID,
+    pub track_name: String,
+    pub artist_names: Vec<String>,
+    pub song_id: SongID,
+    pub anime_ann_id: AnnAnimeID,
+    pub anime_eng_name: Option<String>,
+    pub anime_jpn_name: Option<String>,
+    pub certainty: i32,
+    pub found_in: String,
+}
@@ -0,0 +1,161 @@
+//! Imports anisongdb season by season, saving a checkpoint after every completed season so a
+//! stopped import resumes where it left off.
+use std::{collections::HashSet, time::Duration};
+
+use anilist_api::{AnilistAPI, Media};
+use anisong_api::{
+    AnisongAPI,
+    models::{Anisong, Release},
+};
+use log::{error, info, warn};
+use what_anime_shared::AnilistAnimeID;
+
+use crate::{
+    Database,
+    models::{ImportSummary, SeasonImport},
+};
+
+#[derive(Debug, Clone)]
+pub struct SeasonImportConfig {
+    /// Checkpoints and stats are kept per name, so separate imports don't resume each other.
+    pub name: String,
+    pub start: Release,
+    /// Inclusive.
+    pub end: Release,
+    /// Continue after the saved checkpoint instead of at `start` when there is one.
+    pub resume: bool,
+    pub max_attempts: u32,
+    /// Doubled after every failed attempt.
+    pub backoff: Duration,
+}
+
+impl SeasonImportConfig {
+    pub fn new(name: String, start: Release, end: Release) -> Self {
+        Self {
+            name,
+            start,
+            end,
+            resume: true,
+            max_attempts: 5,
+            backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Imports the anisongs together with the anilist media of their anime.
+pub async fn import_with_media<D, B>(db: &D, anilist: &B, anisongs: Vec<Anisong>) -> ImportSummary
+where
+    D: Database,
+    B: AnilistAPI,
+{
+    let ids: HashSet<AnilistAnimeID> = anisongs
+        .iter()
+        .filter_map(|a| a.anime.linked_ids.anilist)
+        .collect();
+
+    let mut media: Vec<Media> = Vec::with_capacity(ids.len());
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    let ids: Vec<_> = ids.into_iter().collect();
+    for chunk in ids.chunks(50) {
+        ticker.tick().await;
+        let mut new = match anilist.fetch_many(chunk.to_vec()).await {
+            Ok(m) => m,
+            Err(e) => {
+                error!("Got error from anilist_api, Error {:?}", e);
+                vec![]
+            }
+        };
+        media.append(&mut new);
+    }
+
+    db.add_from_anisongs(anisongs, media).await
+}
+
+/// Walks the seasons from the start or checkpoint up to the end. Stops at the first season that
+/// fails every attempt, so the checkpoint never skips past a missing season.
+pub async fn import_seasons<D, A, B>(
+    db: &D,
+    anisong: &A,
+    anilist: &B,
+    config: SeasonImportConfig,
+) -> Vec<SeasonImport>
+where
+    D: Database,
+    A: AnisongAPI,
+    B: AnilistAPI,
+{
+    let mut release = config.start.clone();
+    if config.resume
+        && let Some(checkpoint) = db.get_import_checkpoint(config.name.clone()).await
+        && checkpoint >= release
+    {
+        release = checkpoint.next();
+    }
+
+    let mut imports = Vec::new();
+    while release <= config.end {
+        let import = import_season(db, anisong, anilist, &config, &release).await;
+        let failed = import.error.is_some();
+        db.save_season_import(import.clone()).await;
+        imports.push(import);
+        if failed {
+            break;
+        }
+        release = release.next();
+    }
+    imports
+}
+
+async fn import_season<D, A, B>(
+    db: &D,
+    anisong: &A,
+    anilist: &B,
+    config: &SeasonImportConfig,
+    release: &Release,
+) -> SeasonImport
+where
+    D: Database,
+    A: AnisongAPI,
+    B: AnilistAPI,
+{
+    let mut import = SeasonImport {
+        name: config.name.clone(),
+        season: release.season,
+        year: release.year,
+        attempts: 0,
+        songs: 0,
+        summary: ImportSummary::default(),
+        error: None,
+        finished_at: chrono::Utc::now(),
+    };
+    let mut backoff = config.backoff;
+    let anisongs = loop {
+        import.attempts += 1;
+        match anisong.get_anime_season(release.clone()).await {
+            Ok(anisongs) => break anisongs,
+            Err(e) if import.attempts as u32 >= config.max_attempts => {
+                error!(
+                    "Giving up on {} after {} attempts",
+                    release, import.attempts
+                );
+                import.error = Some(format!("{:?}", e));
+                import.finished_at = chrono::Utc::now();
+                return import;
+            }
+            Err(e) => {
+                warn!(
+                    "Fetching {} failed, retrying in {:?}: {:?}",
+                    release, backoff, e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+    };
+
+    import.songs = anisongs.len() as i32;
+    import.summary = import_with_media(db, anilist, anisongs).await;
+    import.finished_at = chrono::Utc::now();
+    info!("Imported {}: {:?}", release, import.summary);
+    import
+}
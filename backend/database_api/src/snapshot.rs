@@ -0,0 +1,327 @@
+//! Portable backups of the anisong data and the community made spotify links. A snapshot is a
+//! directory with a `manifest.json` and one json lines or csv file per table.
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, Postgres};
+
+use crate::{Database, models::SnapshotTable};
+
+/// Bumped whenever the layout of a snapshot changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+const PAGE_SIZE: i64 = 5000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotFormat {
+    Jsonl,
+    Csv,
+}
+
+impl SnapshotFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Jsonl => "jsonl",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotTableInfo {
+    pub table: SnapshotTable,
+    pub file: String,
+    pub rows: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    pub format: SnapshotFormat,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub tables: Vec<SnapshotTableInfo>,
+}
+
+/// Where `Database::restore_snapshot` reads a table from.
+#[derive(Debug, Clone)]
+pub enum SnapshotSource {
+    Jsonl(PathBuf),
+    Csv(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Db(sqlx::Error),
+    UnsupportedVersion(u32),
+    /// Restores keep the ids of the snapshot, so they only go into empty tables.
+    NotEmpty(SnapshotTable),
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<sqlx::Error> for SnapshotError {
+    fn from(value: sqlx::Error) -> Self {
+        Self::Db(value)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+/// Counts the records of postgres csv output passing through, quoted fields may contain newlines.
+pub(crate) struct CsvRecords<W> {
+    inner: W,
+    in_quotes: bool,
+    lines: u64,
+}
+
+impl<W> CsvRecords<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            in_quotes: false,
+            lines: 0,
+        }
+    }
+
+    pub(crate) fn records(&self) -> u64 {
+        // Minus the header.
+        self.lines.saturating_sub(1)
+    }
+}
+
+impl<W: Write> Write for CsvRecords<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for byte in &buf[..written] {
+            match byte {
+                b'"' => self.in_quotes = !self.in_quotes,
+                b'\n' if !self.in_quotes => self.lines += 1,
+                _ => {}
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes every snapshot table into `dir`, which is created when missing.
+pub async fn export_snapshot<D: Database>(
+    db: &D,
+    dir: &Path,
+    format: SnapshotFormat,
+) -> Result<SnapshotManifest, SnapshotError> {
+    std::fs::create_dir_all(dir)?;
+    let mut tables = Vec::with_capacity(SnapshotTable::ALL.len());
+    for table in SnapshotTable::ALL {
+        let file = format!("{}.{}", table.name(), format.extension());
+        let rows = match format {
+            SnapshotFormat::Jsonl => {
+                let mut writer = BufWriter::new(File::create(dir.join(&file))?);
+                let mut rows = 0;
+                let mut after = None;
+                loop {
+                    let page = db.export_rows(table, after, PAGE_SIZE).await;
+                    for row in &page {
+                        serde_json::to_writer(&mut writer, row)?;
+                        writer.write_all(b"\n")?;
+                    }
+                    rows += page.len() as u64;
+                    if (page.len() as i64) < PAGE_SIZE {
+                        break;
+                    }
+                    after = page.last().cloned();
+                }
+                writer.flush()?;
+                rows
+            }
+            SnapshotFormat::Csv => {
+                let mut writer = CsvRecords::new(BufWriter::new(File::create(dir.join(&file))?));
+                db.export_csv(table, &mut writer).await?;
+                writer.flush()?;
+                writer.records()
+            }
+        };
+        tables.push(SnapshotTableInfo { table, file, rows });
+    }
+
+    let manifest = SnapshotManifest {
+        version: SNAPSHOT_VERSION,
+        format,
+        created_at: chrono::Utc::now(),
+        tables,
+    };
+    std::fs::write(dir.join(MANIFEST), serde_json::to_string_pretty(&manifest)?)?;
+    Ok(manifest)
+}
+
+/// Restores the snapshot in `dir` in one transaction. Links point at the ids in the snapshot, so
+/// the tables have to be empty, a fresh database after migrations. Returns the rows added per
+/// table.
+pub async fn import_snapshot<D: Database>(
+    db: &D,
+    dir: &Path,
+) -> Result<Vec<(SnapshotTable, u64)>, SnapshotError> {
+    let manifest: SnapshotManifest =
+        serde_json::from_str(&std::fs::read_to_string(dir.join(MANIFEST))?)?;
+    if manifest.version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(manifest.version));
+    }
+
+    // Restored in the order of `ALL` regardless of the manifest, so links follow their targets.
+    let tables = SnapshotTable::ALL
+        .into_iter()
+        .filter_map(|table| {
+            let info = manifest.tables.iter().find(|t| t.table == table)?;
+            let path = dir.join(&info.file);
+            let source = match manifest.format {
+                SnapshotFormat::Jsonl => SnapshotSource::Jsonl(path),
+                SnapshotFormat::Csv => SnapshotSource::Csv(path),
+            };
+            Some((table, source))
+        })
+        .collect();
+    db.restore_snapshot(tables).await
+}
+
+/// Runs inside the transaction of `Database::restore_snapshot`.
+pub(crate) async fn restore_tables(
+    conn: &mut PgConnection,
+    tables: Vec<(SnapshotTable, SnapshotSource)>,
+) -> Result<Vec<(SnapshotTable, u64)>, SnapshotError> {
+    for (table, _) in &tables {
+        let exists = sqlx::query_scalar::<Postgres, bool>(&format!(
+            "SELECT EXISTS (SELECT 1 FROM {})",
+            table.name()
+        ))
+        .fetch_one(&mut *conn)
+        .await?;
+        if exists {
+            return Err(SnapshotError::NotEmpty(*table));
+        }
+    }
+
+    let mut inserted = Vec::with_capacity(tables.len());
+    for (table, source) in tables {
+        let count = match source {
+            SnapshotSource::Jsonl(path) => {
+                let mut count = 0;
+                let mut rows = Vec::with_capacity(PAGE_SIZE as usize);
+                for line in BufReader::new(File::open(path)?).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    rows.push(serde_json::from_str(&line)?);
+                    if rows.len() == PAGE_SIZE as usize {
+                        count += insert_rows(conn, table, std::mem::take(&mut rows)).await?;
+                    }
+                }
+                if !rows.is_empty() {
+                    count += insert_rows(conn, table, rows).await?;
+                }
+                count
+            }
+            SnapshotSource::Csv(path) => copy_csv(conn, table, &path).await?,
+        };
+        if table == SnapshotTable::Songs {
+            sync_song_id_sequence(conn).await?;
+        }
+        inserted.push((table, count));
+    }
+    sqlx::query::<Postgres>("SELECT refresh_anisong_view_all()")
+        .execute(&mut *conn)
+        .await?;
+    Ok(inserted)
+}
+
+async fn insert_rows(
+    conn: &mut PgConnection,
+    table: SnapshotTable,
+    rows: Vec<serde_json::Value>,
+) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query::<Postgres>(&format!(
+        "INSERT INTO {0} SELECT * FROM jsonb_populate_recordset(NULL::{0}, $1) ON CONFLICT DO NOTHING",
+        table.name()
+    ))
+    .bind(serde_json::Value::Array(rows))
+    .execute(&mut *conn)
+    .await?
+    .rows_affected())
+}
+
+async fn copy_csv(
+    conn: &mut PgConnection,
+    table: SnapshotTable,
+    path: &Path,
+) -> Result<u64, SnapshotError> {
+    let file = tokio::fs::File::open(path).await?;
+    // COPY can't skip conflicts, so the rows go through a scratch table first.
+    sqlx::query::<Postgres>(&format!(
+        "CREATE TEMP TABLE snapshot_import (LIKE {} INCLUDING DEFAULTS)",
+        table.name()
+    ))
+    .execute(&mut *conn)
+    .await?;
+    let mut copy = conn
+        .copy_in_raw("COPY snapshot_import FROM STDIN WITH (FORMAT csv, HEADER)")
+        .await?;
+    copy.read_from(file).await?;
+    copy.finish().await?;
+    let inserted = sqlx::query::<Postgres>(&format!(
+        "INSERT INTO {} SELECT * FROM snapshot_import ON CONFLICT DO NOTHING",
+        table.name()
+    ))
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    sqlx::query::<Postgres>("DROP TABLE snapshot_import")
+        .execute(&mut *conn)
+        .await?;
+    Ok(inserted)
+}
+
+/// Restored songs keep their ids, so the sequence has to continue after the largest one.
+async fn sync_song_id_sequence(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query::<Postgres>(
+        "SELECT setval(pg_get_serial_sequence('songs', 'id'), COALESCE(MAX(id), 1)) FROM songs",
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_records() {
+        let csv = b"id,names\n1,\"{\"\"a\nb\"\"}\"\n2,{c}\n";
+        let mut records = CsvRecords::new(Vec::new());
+        // Split inside the quoted field, the quote state carries over between writes.
+        records.write_all(&csv[..14]).unwrap();
+        records.write_all(&csv[14..]).unwrap();
+        assert_eq!(records.records(), 2);
+        let mut header = CsvRecords::new(Vec::new());
+        header.write_all(b"id,names\n").unwrap();
+        assert_eq!(header.records(), 0);
+    }
+}
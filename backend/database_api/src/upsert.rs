@@ -0,0 +1,436 @@
+use anilist_api::models::TagID;
+use anisong_api::models::{AnisongArtistID, AnnAnimeID};
+use sqlx::{PgConnection, Postgres, QueryBuilder, Row};
+use what_anime_shared::{SongID, URL};
+
+use crate::models::{DBAnime, DBAnisongBind, SimplifiedAnisongSong, SimplifiedArtist, UpsertCount};
+
+// Every upsert below only touches a conflicting row when the merged values differ from the stored
+// ones, so rows skipped by the WHERE clause are the unchanged ones.
+
+/// (column, value it takes on conflict)
+const ANIME_MERGE: &[(&str, &str)] = &[
+    ("eng_name", "COALESCE(EXCLUDED.eng_name, animes.eng_name)"),
+    ("jpn_name", "COALESCE(EXCLUDED.jpn_name, animes.jpn_name)"),
+    (
+        "alt_names",
+        "array_unique(EXCLUDED.alt_names, animes.alt_names)",
+    ),
+    (
+        "myanimelist_id",
+        "COALESCE(EXCLUDED.myanimelist_id, animes.myanimelist_id)",
+    ),
+    ("anidb_id", "COALESCE(EXCLUDED.anidb_id, animes.anidb_id)"),
+    (
+        "anilist_id",
+        "COALESCE(EXCLUDED.anilist_id, animes.anilist_id)",
+    ),
+    ("kitsu_id", "COALESCE(EXCLUDED.kitsu_id, animes.kitsu_id)"),
+    (
+        "anime_type",
+        "COALESCE(EXCLUDED.anime_type, animes.anime_type)",
+    ),
+    (
+        "index_type",
+        "COALESCE(EXCLUDED.index_type, animes.index_type)",
+    ),
+    (
+        "index_number",
+        "COALESCE(EXCLUDED.index_number, animes.index_number)",
+    ),
+    (
+        "index_part",
+        "COALESCE(EXCLUDED.index_part, animes.index_part)",
+    ),
+    (
+        "mean_score",
+        "COALESCE(EXCLUDED.mean_score, animes.mean_score)",
+    ),
+    (
+        "banner_image",
+        "COALESCE(EXCLUDED.banner_image, animes.banner_image)",
+    ),
+    (
+        "cover_image_color",
+        "COALESCE(EXCLUDED.cover_image_color, animes.cover_image_color)",
+    ),
+    (
+        "cover_image_medium",
+        "COALESCE(EXCLUDED.cover_image_medium, animes.cover_image_medium)",
+    ),
+    (
+        "cover_image_large",
+        "COALESCE(EXCLUDED.cover_image_large, animes.cover_image_large)",
+    ),
+    (
+        "cover_image_extra_large",
+        "COALESCE(EXCLUDED.cover_image_extra_large, animes.cover_image_extra_large)",
+    ),
+    ("format", "COALESCE(EXCLUDED.format, animes.format)"),
+    // Empty arrays mean anilist had nothing for us, not that the data was removed
+    (
+        "genres",
+        "COALESCE(NULLIF(EXCLUDED.genres, '{}'), animes.genres)",
+    ),
+    ("source", "COALESCE(EXCLUDED.source, animes.source)"),
+    (
+        "studios_id",
+        "COALESCE(NULLIF(EXCLUDED.studios_id, '{}'), animes.studios_id)",
+    ),
+    (
+        "studios_name",
+        "COALESCE(NULLIF(EXCLUDED.studios_name, '{}'), animes.studios_name)",
+    ),
+    (
+        "studios_url",
+        "COALESCE(NULLIF(EXCLUDED.studios_url, '{}'), animes.studios_url)",
+    ),
+    (
+        "tags_id",
+        "COALESCE(NULLIF(EXCLUDED.tags_id, '{}'), animes.tags_id)",
+    ),
+    (
+        "tags_name",
+        "COALESCE(NULLIF(EXCLUDED.tags_name, '{}'), animes.tags_name)",
+    ),
+    (
+        "trailer_id",
+        "COALESCE(EXCLUDED.trailer_id, animes.trailer_id)",
+    ),
+    (
+        "trailer_site",
+        "COALESCE(EXCLUDED.trailer_site, animes.trailer_site)",
+    ),
+    (
+        "trailer_thumbnail",
+        "COALESCE(EXCLUDED.trailer_thumbnail, animes.trailer_thumbnail)",
+    ),
+    ("episodes", "COALESCE(EXCLUDED.episodes, animes.episodes)"),
+    ("season", "COALESCE(EXCLUDED.season, animes.season)"),
+    (
+        "season_year",
+        "COALESCE(EXCLUDED.season_year, animes.season_year)",
+    ),
+    (
+        "vintage_release_season",
+        "COALESCE(EXCLUDED.vintage_release_season, animes.vintage_release_season)",
+    ),
+    (
+        "vintage_release_year",
+        "COALESCE(EXCLUDED.vintage_release_year, animes.vintage_release_year)",
+    ),
+];
+
+const ARTIST_MERGE: &[(&str, &str)] = &[
+    ("names", "array_unique(artists.names, EXCLUDED.names)"),
+    (
+        "group_ids",
+        "array_unique(artists.group_ids, EXCLUDED.group_ids)",
+    ),
+    (
+        "member_ids",
+        "array_unique(artists.member_ids, EXCLUDED.member_ids)",
+    ),
+    ("line_up_id", "EXCLUDED.line_up_id"),
+];
+
+const SONG_MERGE: &[(&str, &str)] = &[
+    ("artist_name", "EXCLUDED.artist_name"),
+    ("composer_name", "EXCLUDED.composer_name"),
+    ("arranger_name", "EXCLUDED.arranger_name"),
+    ("category", "EXCLUDED.category"),
+    ("length", "COALESCE(EXCLUDED.length, songs.length)"),
+    ("is_dub", "EXCLUDED.is_dub"),
+    ("hq", "COALESCE(EXCLUDED.hq, songs.hq)"),
+    ("mq", "COALESCE(EXCLUDED.mq, songs.mq)"),
+    ("audio", "COALESCE(EXCLUDED.audio, songs.audio)"),
+    ("composers", "EXCLUDED.composers"),
+    ("arrangers", "EXCLUDED.arrangers"),
+];
+
+const ANISONG_BIND_MERGE: &[(&str, &str)] = &[
+    ("song_id", "EXCLUDED.song_id"),
+    ("anime_ann_id", "EXCLUDED.anime_ann_id"),
+    (
+        "difficulty",
+        "COALESCE(EXCLUDED.difficulty, anime_song_links.difficulty)",
+    ),
+    ("song_index_type", "EXCLUDED.song_index_type"),
+    ("song_index_number", "EXCLUDED.song_index_number"),
+    ("is_rebroadcast", "EXCLUDED.is_rebroadcast"),
+];
+
+/// Builds `SET a = x, b = y WHERE (table.a, table.b) IS DISTINCT FROM (x, y)`
+fn merge_clause(table: &str, merge: &[(&str, &str)]) -> String {
+    let set = merge
+        .iter()
+        .map(|(column, value)| format!("{column} = {value}"))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let columns = merge
+        .iter()
+        .map(|(column, _)| format!("{table}.{column}"))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let values = merge
+        .iter()
+        .map(|(_, value)| *value)
+        .collect::<Vec<&str>>()
+        .join(", ");
+    format!(" SET {set} WHERE ({columns}) IS DISTINCT FROM ({values})")
+}
+
+/// `inserted` holds one flag per returned row, true for inserts and false for updates.
+fn count(total: usize, inserted: Vec<bool>) -> UpsertCount {
+    let touched = inserted.len() as u64;
+    let inserted = inserted.into_iter().filter(|i| *i).count() as u64;
+    UpsertCount {
+        inserted,
+        updated: touched - inserted,
+        unchanged: total as u64 - touched,
+    }
+}
+
+pub async fn upsert_animes(conn: &mut PgConnection, animes: Vec<DBAnime>) -> UpsertCount {
+    if animes.is_empty() {
+        return UpsertCount::default();
+    }
+    let total = animes.len();
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        r#"INSERT INTO animes (
+        ann_id, eng_name, jpn_name, alt_names, myanimelist_id, anidb_id, anilist_id, kitsu_id, anime_type, index_type, index_number,
+        index_part, mean_score, banner_image, cover_image_color, cover_image_medium, cover_image_large, cover_image_extra_large, format,
+        genres, source, studios_id, studios_name, studios_url, tags_id, tags_name, trailer_id, trailer_site, trailer_thumbnail, episodes,
+        season, season_year, vintage_release_season, vintage_release_year
+        )
+        "#,
+    );
+    query_builder.push_values(animes, |mut builder, anime| {
+        let (tag_ids, tag_names): (Vec<TagID>, Vec<String>) =
+            anime.tags.into_iter().map(|t| (t.id, t.name)).unzip();
+        let (studio_ids, studio_info): (Vec<i32>, Vec<(String, Option<URL>)>) = anime
+            .studios
+            .nodes
+            .into_iter()
+            .map(|a| (a.id, (a.name, a.site_url)))
+            .unzip();
+
+        let (studio_names, studio_urls): (Vec<String>, Vec<Option<URL>>) =
+            studio_info.into_iter().unzip();
+
+        builder
+            .push_bind(anime.ann_id)
+            .push_bind(anime.eng_name)
+            .push_bind(anime.jpn_name)
+            .push_bind(anime.alt_name)
+            .push_bind(anime.linked_ids.myanimelist)
+            .push_bind(anime.linked_ids.anidb)
+            .push_bind(anime.linked_ids.anilist)
+            .push_bind(anime.linked_ids.kitsu)
+            .push_bind(anime.anime_type)
+            .push_bind(anime.anime_index.index_type)
+            .push_bind(anime.anime_index.number)
+            .push_bind(anime.anime_index.part)
+            .push_bind(anime.mean_score)
+            .push_bind(anime.banner_image)
+            .push_bind(anime.cover_image.color)
+            .push_bind(anime.cover_image.medium)
+            .push_bind(anime.cover_image.large)
+            .push_bind(anime.cover_image.extra_large)
+            .push_bind(anime.format)
+            .push_bind(anime.genres)
+            .push_bind(anime.source)
+            .push_bind(studio_ids)
+            .push_bind(studio_names)
+            .push_bind(studio_urls)
+            .push_bind(tag_ids)
+            .push_bind(tag_names)
+            .push_bind(anime.trailer.as_ref().map(|t| t.id.clone()))
+            .push_bind(anime.trailer.as_ref().map(|t| t.site.clone()))
+            .push_bind(anime.trailer.as_ref().map(|t| t.thumbnail.clone()))
+            .push_bind(anime.episodes)
+            .push_bind(anime.season)
+            .push_bind(anime.season_year)
+            .push_bind(anime.vintage.as_ref().map(|v| v.season))
+            .push_bind(anime.vintage.map(|v| v.year));
+    });
+    query_builder.push(" ON CONFLICT ( ann_id ) DO UPDATE");
+    query_builder.push(merge_clause("animes", ANIME_MERGE));
+    query_builder.push(" RETURNING (xmax = 0) AS inserted");
+
+    let inserted = query_builder
+        .build_query_scalar::<bool>()
+        .fetch_all(&mut *conn)
+        .await
+        .unwrap();
+    count(total, inserted)
+}
+
+pub async fn upsert_artists(
+    conn: &mut PgConnection,
+    artists: Vec<SimplifiedArtist>,
+) -> UpsertCount {
+    if artists.is_empty() {
+        return UpsertCount::default();
+    }
+    let total = artists.len();
+    let mut query_builder: QueryBuilder<Postgres> =
+        QueryBuilder::new("INSERT INTO artists (id, names, line_up_id, group_ids, member_ids) ");
+    query_builder.push_values(artists, |mut builder, artist| {
+        builder
+            .push_bind(artist.id)
+            .push_bind(artist.names)
+            .push_bind(artist.line_up_id)
+            .push_bind(artist.group_ids)
+            .push_bind(artist.member_ids);
+    });
+    query_builder.push(" ON CONFLICT ( id ) DO UPDATE");
+    query_builder.push(merge_clause("artists", ARTIST_MERGE));
+    query_builder.push(" RETURNING (xmax = 0) AS inserted");
+
+    let inserted = query_builder
+        .build_query_scalar::<bool>()
+        .fetch_all(&mut *conn)
+        .await
+        .unwrap();
+    count(total, inserted)
+}
+
+/// Returns the ids of the given songs in input order, whether or not they changed.
+pub async fn upsert_songs(
+    conn: &mut PgConnection,
+    songs: Vec<SimplifiedAnisongSong>,
+) -> (Vec<SongID>, UpsertCount) {
+    if songs.is_empty() {
+        return (vec![], UpsertCount::default());
+    }
+    let total = songs.len();
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        r#"WITH data (temp_order, name, artist_name, composer_name, arranger_name, category, length, is_dub, hq, mq, audio, artists, composers, arrangers) AS ("#,
+    );
+    query_builder.push_values(songs.into_iter().enumerate(), |mut builder, song| {
+        builder
+            .push_bind(song.0 as i64)
+            .push_bind(song.1.name)
+            .push_bind(song.1.artist_name)
+            .push_bind(song.1.composer_name)
+            .push_bind(song.1.arranger_name)
+            .push_bind(song.1.category)
+            .push_bind(song.1.length)
+            .push_bind(song.1.is_dub)
+            .push_bind(song.1.hq)
+            .push_bind(song.1.mq)
+            .push_bind(song.1.audio)
+            .push_bind(
+                song.1
+                    .artists
+                    .iter()
+                    .map(|a| a.id)
+                    .collect::<Vec<AnisongArtistID>>(),
+            )
+            .push_bind(
+                song.1
+                    .composers
+                    .iter()
+                    .map(|a| a.id)
+                    .collect::<Vec<AnisongArtistID>>(),
+            )
+            .push_bind(
+                song.1
+                    .arrangers
+                    .iter()
+                    .map(|a| a.id)
+                    .collect::<Vec<AnisongArtistID>>(),
+            );
+    });
+    query_builder.push(
+        r#"), upserted AS (
+            INSERT INTO songs (name, artist_name, composer_name, arranger_name, category, length, is_dub, hq, mq, audio, artists, composers, arrangers)
+                SELECT name, artist_name, composer_name, arranger_name, category, length, is_dub, hq, mq, audio, artists, composers, arrangers FROM data
+                ON CONFLICT (name, sort_int_array(artists)) DO UPDATE"#,
+    );
+    query_builder.push(merge_clause("songs", SONG_MERGE));
+    // Unchanged songs are not returned by the upsert, so their ids come from the old snapshot
+    query_builder.push(
+        r#"
+                RETURNING id, name, artists, (xmax = 0) AS inserted
+        )
+        SELECT COALESCE(u.id, s.id) AS id, u.inserted
+        FROM data d
+        LEFT JOIN upserted u
+            ON u.name = d.name AND sort_int_array(u.artists) = sort_int_array(d.artists)
+        LEFT JOIN LATERAL (
+            SELECT id FROM songs s
+            WHERE s.name = d.name AND sort_int_array(s.artists) = sort_int_array(d.artists)
+            ORDER BY s.id
+            LIMIT 1
+        ) s ON true
+        ORDER BY d.temp_order;
+        "#,
+    );
+
+    let rows = query_builder.build().fetch_all(&mut *conn).await.unwrap();
+    let ids = rows.iter().map(|r| r.get::<SongID, _>("id")).collect();
+    let inserted = rows
+        .iter()
+        .filter_map(|r| r.get::<Option<bool>, _>("inserted"))
+        .collect();
+    (ids, count(total, inserted))
+}
+
+pub async fn upsert_anisong_binds(
+    conn: &mut PgConnection,
+    binds: Vec<DBAnisongBind>,
+) -> UpsertCount {
+    if binds.is_empty() {
+        return UpsertCount::default();
+    }
+    let total = binds.len();
+    let mut query_builder: QueryBuilder<'_, Postgres> = QueryBuilder::new(
+        "INSERT INTO anime_song_links (song_id, anime_ann_id, song_ann_id, difficulty, song_index_type, song_index_number, is_rebroadcast) ",
+    );
+
+    query_builder.push_values(binds, |mut builder, bind| {
+        assert!(bind.song_id.is_some());
+        assert!(bind.anime_ann_id.is_some());
+
+        builder
+            .push_bind(bind.song_id)
+            .push_bind(bind.anime_ann_id)
+            .push_bind(bind.song_ann_id)
+            .push_bind(bind.difficulty)
+            .push_bind(bind.song_index.index_type)
+            .push_bind(bind.song_index.number)
+            .push_bind(bind.is_rebroadcast);
+    });
+
+    query_builder.push(" ON CONFLICT ( song_ann_id ) DO UPDATE");
+    query_builder.push(merge_clause("anime_song_links", ANISONG_BIND_MERGE));
+    query_builder.push(" RETURNING (xmax = 0) AS inserted");
+
+    let inserted = query_builder
+        .build_query_scalar::<bool>()
+        .fetch_all(&mut *conn)
+        .await
+        .unwrap();
+    count(total, inserted)
+}
+
+/// Rebuilds the `anisong_view` rows that reference any of the given songs, animes or artists.
+pub async fn refresh_anisong_view_for(
+    conn: &mut PgConnection,
+    song_ids: Vec<SongID>,
+    anime_ids: Vec<AnnAnimeID>,
+    artist_ids: Vec<AnisongArtistID>,
+) -> u64 {
+    if song_ids.is_empty() && anime_ids.is_empty() && artist_ids.is_empty() {
+        return 0;
+    }
+    sqlx::query_scalar::<Postgres, i64>("SELECT refresh_anisong_view($1, $2, $3)")
+        .bind(song_ids)
+        .bind(anime_ids)
+        .bind(artist_ids)
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap() as u64
+}
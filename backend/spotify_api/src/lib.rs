@@ -3,23 +3,32 @@ use std::str::FromStr;
 
 use base64::{Engine, engine};
 use models::{
-    ClientID, ClientSecret, CurrentlyPlaying, Item, Response, SpotifyError, SpotifyToken, State,
-    TokenResponse, TrackObject,
+    ClientID, ClientSecret, CurrentlyPlaying, Item, Paging, PlayHistory, PlaybackState, Playlist,
+    PlaylistID, PlaylistTrack, Queue, Response, SavedTrack, SimplifiedPlaylist, SpotifyError,
+    SpotifyToken, State, TokenResponse, TrackObject,
 };
 use rand::Rng;
 use reqwest::{
     StatusCode, Url,
     header::{HeaderMap, HeaderValue},
 };
-use serde::Serialize;
-use what_anime_shared::{SpotifyTrackID, SpotifyUser};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use what_anime_shared::{SpotifyTrackID, SpotifyUser, SpotifyUserID};
+
+/// Everything the app asks for at login, the library scan needs the library and playlist reads.
+pub const SCOPES: &str = "user-read-private user-read-email user-read-playback-state user-read-currently-playing user-read-recently-played user-library-read playlist-read-private playlist-modify-public playlist-modify-private";
 
 // use tokio::time::{Duration, Interval, interval};
 pub trait SpotifyAPI {
+    /// The playback state is `None` when there is no active device.
     fn get_current(
         &self,
         token: SpotifyToken,
-    ) -> impl std::future::Future<Output = Result<CurrentlyPlaying, models::Error>> + Send;
+    ) -> impl std::future::Future<
+        Output = Result<(CurrentlyPlaying, Option<PlaybackState>), models::Error>,
+    > + Send;
     fn get_user(
         &self,
         token: SpotifyToken,
@@ -36,6 +45,50 @@ pub trait SpotifyAPI {
         client_id: ClientID,
         client_secret: ClientSecret,
     ) -> impl std::future::Future<Output = Result<TokenResponse, models::Error>> + Send;
+    fn create_playlist(
+        &self,
+        token: SpotifyToken,
+        user_id: SpotifyUserID,
+        name: String,
+        description: String,
+        public: bool,
+    ) -> impl std::future::Future<Output = Result<Playlist, models::Error>> + Send;
+    /// Appends the tracks in order, split into requests of at most 100 tracks.
+    fn add_tracks(
+        &self,
+        token: SpotifyToken,
+        playlist_id: PlaylistID,
+        tracks: Vec<SpotifyTrackID>,
+    ) -> impl std::future::Future<Output = Result<(), models::Error>> + Send;
+    fn get_saved_tracks(
+        &self,
+        token: SpotifyToken,
+        offset: u32,
+        limit: u32,
+    ) -> impl std::future::Future<Output = Result<Paging<SavedTrack>, models::Error>> + Send;
+    fn get_playlists(
+        &self,
+        token: SpotifyToken,
+        offset: u32,
+        limit: u32,
+    ) -> impl std::future::Future<Output = Result<Paging<SimplifiedPlaylist>, models::Error>> + Send;
+    fn get_playlist_tracks(
+        &self,
+        token: SpotifyToken,
+        playlist_id: PlaylistID,
+        offset: u32,
+        limit: u32,
+    ) -> impl std::future::Future<Output = Result<Paging<PlaylistTrack>, models::Error>> + Send;
+    /// Newest first, spotify allows at most 50.
+    fn get_recently_played(
+        &self,
+        token: SpotifyToken,
+        limit: u32,
+    ) -> impl std::future::Future<Output = Result<Vec<PlayHistory>, models::Error>> + Send;
+    fn get_queue(
+        &self,
+        token: SpotifyToken,
+    ) -> impl std::future::Future<Output = Result<Queue, models::Error>> + Send;
     fn generate_login_link(&self, client_id: ClientID, redirect_uri: Url) -> (State, Url);
     fn handle_callback(
         &self,
@@ -49,6 +102,7 @@ pub trait SpotifyAPI {
 pub struct SpotifyAPIR<const ALLOWED_FETCH_PER_SEC: u64> {
     client: reqwest::Client,
     //ticker: Interval,
+    next_fetch: Mutex<Instant>,
 }
 
 impl<const ALLOWED_FETCH_PER_SEC: u64> SpotifyAPIR<ALLOWED_FETCH_PER_SEC> {
@@ -56,6 +110,34 @@ impl<const ALLOWED_FETCH_PER_SEC: u64> SpotifyAPIR<ALLOWED_FETCH_PER_SEC> {
         Self {
             client: reqwest::Client::new(),
             //ticker: interval(Duration::from_millis(1000 / ALLOWED_FETCH_PER_SEC)),
+            next_fetch: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until another request fits within `ALLOWED_FETCH_PER_SEC`.
+    async fn wait_for_slot(&self) {
+        let mut next_fetch = self.next_fetch.lock().await;
+        tokio::time::sleep_until(*next_fetch).await;
+        *next_fetch = Instant::now() + Duration::from_millis(1000 / ALLOWED_FETCH_PER_SEC);
+    }
+
+    /// Rate limited GET for the library and player endpoints.
+    async fn get_page<T: DeserializeOwned>(
+        &self,
+        token: SpotifyToken,
+        url: Url,
+    ) -> Result<T, models::Error> {
+        self.wait_for_slot().await;
+        let response = self
+            .client
+            .get(url)
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => Ok(response.json().await?),
+            _ => Err(Self::handle_error_status(response).await),
         }
     }
 
@@ -76,7 +158,10 @@ impl<const ALLOWED_FETCH_PER_SEC: u64> SpotifyAPIR<ALLOWED_FETCH_PER_SEC> {
 }
 
 impl<const ALLOWED_FETCH_PER_SEC: u64> SpotifyAPI for SpotifyAPIR<ALLOWED_FETCH_PER_SEC> {
-    async fn get_current(&self, token: SpotifyToken) -> Result<CurrentlyPlaying, models::Error> {
+    async fn get_current(
+        &self,
+        token: SpotifyToken,
+    ) -> Result<(CurrentlyPlaying, Option<PlaybackState>), models::Error> {
         //self.ticker.tick().await;
         let mut headers = HeaderMap::new();
         headers.insert(
@@ -84,8 +169,9 @@ impl<const ALLOWED_FETCH_PER_SEC: u64> SpotifyAPI for SpotifyAPIR<ALLOWED_FETCH_
             HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
         );
 
-        let url = Url::from_str("https://api.spotify.com/v1/me/player/currently-playing")
-            .expect("Invalid URL");
+        let url =
+            Url::from_str("https://api.spotify.com/v1/me/player?additional_types=track,episode")
+                .expect("Invalid URL");
 
         let response = self
             .client
@@ -96,13 +182,16 @@ impl<const ALLOWED_FETCH_PER_SEC: u64> SpotifyAPI for SpotifyAPIR<ALLOWED_FETCH_
             .unwrap();
 
         match response.status() {
-            StatusCode::NO_CONTENT => Ok(CurrentlyPlaying::Nothing),
+            StatusCode::NO_CONTENT => Ok((CurrentlyPlaying::Nothing, None)),
             StatusCode::OK => {
-                let t: Response = response.json().await.unwrap();
-                match t.item {
-                    Item::TrackObject(t) => Ok(CurrentlyPlaying::Track(t)),
-                    Item::EpisodeObject => Ok(CurrentlyPlaying::Episode),
-                }
+                let t: Response = response.json().await?;
+                let playing = match t.item {
+                    Some(Item::TrackObject(t)) => CurrentlyPlaying::Track(t),
+                    Some(Item::LocalTrack(t)) => CurrentlyPlaying::LocalTrack(t),
+                    Some(Item::EpisodeObject(e)) => CurrentlyPlaying::Episode(e),
+                    None => CurrentlyPlaying::Nothing,
+                };
+                Ok((playing, Some(t.state)))
             }
             _ => Err(Self::handle_error_status(response).await),
         }
@@ -193,9 +282,155 @@ impl<const ALLOWED_FETCH_PER_SEC: u64> SpotifyAPI for SpotifyAPIR<ALLOWED_FETCH_
             _ => Err(Self::handle_error_status(token_response).await),
         }
     }
+    async fn create_playlist(
+        &self,
+        token: SpotifyToken,
+        user_id: SpotifyUserID,
+        name: String,
+        description: String,
+        public: bool,
+    ) -> Result<Playlist, models::Error> {
+        #[derive(Serialize)]
+        struct PlaylistData {
+            name: String,
+            description: String,
+            public: bool,
+        }
+
+        let url = reqwest::Url::from_str(&format!(
+            "https://api.spotify.com/v1/users/{}/playlists",
+            user_id
+        ))
+        .expect("Url must be valid");
+
+        let response = self
+            .client
+            .post(url)
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&PlaylistData {
+                name,
+                description,
+                public,
+            })
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::OK | StatusCode::CREATED => Ok(response.json().await?),
+            _ => Err(Self::handle_error_status(response).await),
+        }
+    }
+
+    async fn add_tracks(
+        &self,
+        token: SpotifyToken,
+        playlist_id: PlaylistID,
+        tracks: Vec<SpotifyTrackID>,
+    ) -> Result<(), models::Error> {
+        #[derive(Serialize)]
+        struct TrackData {
+            uris: Vec<String>,
+        }
+
+        let url = reqwest::Url::from_str(&format!(
+            "https://api.spotify.com/v1/playlists/{}/tracks",
+            playlist_id
+        ))
+        .expect("Url must be valid");
+
+        for chunk in tracks.chunks(100) {
+            let response = self
+                .client
+                .post(url.clone())
+                .header("Authorization", format!("Bearer {}", token))
+                .json(&TrackData {
+                    uris: chunk
+                        .iter()
+                        .map(|t| format!("spotify:track:{}", t))
+                        .collect(),
+                })
+                .send()
+                .await?;
+
+            if !matches!(response.status(), StatusCode::OK | StatusCode::CREATED) {
+                return Err(Self::handle_error_status(response).await);
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_saved_tracks(
+        &self,
+        token: SpotifyToken,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Paging<SavedTrack>, models::Error> {
+        let url = Url::parse_with_params(
+            "https://api.spotify.com/v1/me/tracks",
+            [("offset", offset.to_string()), ("limit", limit.to_string())],
+        )
+        .expect("Url must be valid");
+        self.get_page(token, url).await
+    }
+
+    async fn get_playlists(
+        &self,
+        token: SpotifyToken,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Paging<SimplifiedPlaylist>, models::Error> {
+        let url = Url::parse_with_params(
+            "https://api.spotify.com/v1/me/playlists",
+            [("offset", offset.to_string()), ("limit", limit.to_string())],
+        )
+        .expect("Url must be valid");
+        self.get_page(token, url).await
+    }
+
+    async fn get_playlist_tracks(
+        &self,
+        token: SpotifyToken,
+        playlist_id: PlaylistID,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Paging<PlaylistTrack>, models::Error> {
+        let url = Url::parse_with_params(
+            &format!(
+                "https://api.spotify.com/v1/playlists/{}/tracks",
+                playlist_id
+            ),
+            [("offset", offset.to_string()), ("limit", limit.to_string())],
+        )
+        .expect("Url must be valid");
+        self.get_page(token, url).await
+    }
+
+    async fn get_recently_played(
+        &self,
+        token: SpotifyToken,
+        limit: u32,
+    ) -> Result<Vec<PlayHistory>, models::Error> {
+        let url = Url::parse_with_params(
+            "https://api.spotify.com/v1/me/player/recently-played",
+            [("limit", limit.to_string())],
+        )
+        .expect("Url must be valid");
+        // Cursor paged, without the total of the other pages
+        #[derive(Deserialize)]
+        struct CursorPage {
+            items: Vec<PlayHistory>,
+        }
+        let page: CursorPage = self.get_page(token, url).await?;
+        Ok(page.items)
+    }
+
+    async fn get_queue(&self, token: SpotifyToken) -> Result<Queue, models::Error> {
+        let url = Url::from_str("https://api.spotify.com/v1/me/player/queue").expect("Invalid URL");
+        self.get_page(token, url).await
+    }
+
     fn generate_login_link(&self, client_id: ClientID, redirect_uri: Url) -> (State, Url) {
         let random_bytes: [u8; 16] = rand::rng().random();
-        let scope = "user-read-private user-read-email user-read-playback-state user-read-currently-playing";
         let state: State = State(hex::encode(random_bytes));
 
         let auth_params = [
@@ -203,7 +438,7 @@ impl<const ALLOWED_FETCH_PER_SEC: u64> SpotifyAPI for SpotifyAPIR<ALLOWED_FETCH_
             ("response_type", "code".to_string()),
             ("redirect_uri", redirect_uri.to_string()),
             ("state", state.to_string()),
-            ("scope", scope.to_string()),
+            ("scope", SCOPES.to_string()),
         ];
 
         let url = Url::parse_with_params("https://accounts.spotify.com/authorize?", auth_params)
@@ -1,10 +1,11 @@
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
-use what_anime_shared::{ImageURL, SpotifyArtistID, SpotifyTrackID};
+use what_anime_shared::{ImageURL, Isrc, SpotifyArtistID, SpotifyTrackID};
 
 pub enum CurrentlyPlaying {
     Track(TrackObject),
-    Episode,
+    LocalTrack(LocalTrack),
+    Episode(Episode),
     Nothing,
 }
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize, Hash)]
@@ -41,7 +42,40 @@ impl std::fmt::Display for ClientSecret {
 
 #[derive(Deserialize)]
 pub struct Response {
-    pub item: Item,
+    /// `None` while an ad or something else spotify doesn't describe is playing.
+    pub item: Option<Item>,
+    #[serde(flatten)]
+    pub state: PlaybackState,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PlaybackState {
+    pub progress_ms: Option<u64>,
+    pub is_playing: bool,
+    /// Missing while nothing is playing, which spotify treats as off.
+    #[serde(default)]
+    pub shuffle_state: bool,
+    #[serde(default)]
+    pub repeat_state: RepeatState,
+    pub device: Option<Device>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatState {
+    #[default]
+    Off,
+    Track,
+    Context,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Device {
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub device_type: String,
+    pub volume_percent: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -49,26 +83,197 @@ pub struct TokenResponse {
     pub access_token: SpotifyToken,
     pub refresh_token: Option<SpotifyToken>,
     pub expires_in: u64,
+    /// Space separated, missing on tokens stored before it was kept.
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+impl TokenResponse {
+    /// Whether the token was granted every scope in the space separated `scopes`.
+    pub fn has_scopes(&self, scopes: &str) -> bool {
+        let granted: Vec<&str> = self
+            .scope
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect();
+        scopes.split_whitespace().all(|s| granted.contains(&s))
+    }
 }
 
 #[derive(Deserialize)]
 #[serde(untagged)]
 pub enum Item {
     TrackObject(TrackObject),
-    EpisodeObject,
+    LocalTrack(LocalTrack),
+    EpisodeObject(Episode),
+}
+
+/// A local file played through spotify, these have no ids.
+#[derive(Clone, Debug)]
+pub struct LocalTrack {
+    pub uri: String,
+    pub name: String,
+    pub artists: Vec<String>,
+    pub album_name: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl<'de> Deserialize<'de> for LocalTrack {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Artist {
+            name: String,
+        }
+        #[derive(Deserialize)]
+        struct LocalAlbum {
+            name: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Helper {
+            is_local: bool,
+            uri: String,
+            name: String,
+            artists: Vec<Artist>,
+            album: Option<LocalAlbum>,
+            duration_ms: u64,
+        }
+        let h = Helper::deserialize(deserializer)?;
+        if !h.is_local {
+            return Err(serde::de::Error::custom("Track is not local"));
+        }
+        Ok(Self {
+            uri: h.uri,
+            name: h.name,
+            artists: h.artists.into_iter().map(|a| a.name).collect(),
+            album_name: h.album.and_then(|a| a.name),
+            duration_ms: h.duration_ms,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Episode {
+    pub id: String,
+    pub name: String,
+    pub show_name: String,
+    pub image: Option<ImageURL>,
+}
+
+impl<'de> Deserialize<'de> for Episode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Image {
+            url: ImageURL,
+        }
+        #[derive(Deserialize)]
+        struct Show {
+            name: String,
+        }
+        #[derive(Deserialize)]
+        struct Helper {
+            id: String,
+            name: String,
+            show: Show,
+            images: Vec<Image>,
+        }
+        let h = Helper::deserialize(deserializer)?;
+        Ok(Self {
+            id: h.id,
+            name: h.name,
+            show_name: h.show.name,
+            image: h.images.into_iter().next().map(|i| i.url),
+        })
+    }
 }
 
-#[derive(Deserialize)]
 pub struct TrackObject {
     pub album: Album,
     pub artists: Vec<SimplifiedArtist>,
     pub id: SpotifyTrackID,
     pub name: String,
+    pub duration_ms: u64,
+    pub explicit: bool,
+    /// Missing on tracks that are nested in other objects.
+    pub popularity: Option<u32>,
+    pub isrc: Option<Isrc>,
+    pub url: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for TrackObject {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct ExternalIds {
+            isrc: Option<Isrc>,
+        }
+        #[derive(Deserialize)]
+        struct ExternalUrls {
+            spotify: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Helper {
+            album: Album,
+            artists: Vec<SimplifiedArtist>,
+            id: SpotifyTrackID,
+            name: String,
+            duration_ms: u64,
+            explicit: bool,
+            popularity: Option<u32>,
+            external_ids: Option<ExternalIds>,
+            external_urls: Option<ExternalUrls>,
+        }
+        let h = Helper::deserialize(deserializer)?;
+        Ok(Self {
+            album: h.album,
+            artists: h.artists,
+            id: h.id,
+            name: h.name,
+            duration_ms: h.duration_ms,
+            explicit: h.explicit,
+            popularity: h.popularity,
+            isrc: h.external_ids.and_then(|e| e.isrc),
+            url: h.external_urls.and_then(|e| e.spotify),
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AlbumType {
+    #[serde(alias = "ALBUM")]
+    Album,
+    #[serde(alias = "SINGLE")]
+    Single,
+    #[serde(alias = "COMPILATION")]
+    Compilation,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseDatePrecision {
+    Year,
+    Month,
+    Day,
 }
 
 pub struct Album {
+    pub id: Option<String>,
     pub name: String,
+    pub album_type: Option<AlbumType>,
+    /// `YYYY`, `YYYY-MM` or `YYYY-MM-DD` depending on the precision.
+    pub release_date: Option<String>,
+    pub release_date_precision: Option<ReleaseDatePrecision>,
     pub images: Vec<ImageURL>,
+    pub url: Option<String>,
 }
 
 impl<'de> Deserialize<'de> for Album {
@@ -81,14 +286,177 @@ impl<'de> Deserialize<'de> for Album {
             url: ImageURL,
         }
         #[derive(Deserialize)]
+        struct ExternalUrls {
+            spotify: Option<String>,
+        }
+        #[derive(Deserialize)]
         pub struct Helper {
+            pub id: Option<String>,
             pub name: String,
+            pub album_type: Option<AlbumType>,
+            pub release_date: Option<String>,
+            pub release_date_precision: Option<ReleaseDatePrecision>,
             pub images: Vec<Image>,
+            pub external_urls: Option<ExternalUrls>,
         }
         let h = Helper::deserialize(deserializer)?;
         Ok(Self {
+            id: h.id,
             name: h.name,
+            album_type: h.album_type,
+            release_date: h.release_date,
+            release_date_precision: h.release_date_precision,
             images: h.images.into_iter().map(|a| a.url).collect(),
+            url: h.external_urls.and_then(|e| e.spotify),
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize, Hash)]
+pub struct PlaylistID(pub String);
+impl std::fmt::Display for PlaylistID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &self.0)
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Playlist {
+    pub id: PlaylistID,
+    pub name: String,
+    pub url: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for Playlist {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct ExternalUrls {
+            spotify: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Helper {
+            id: PlaylistID,
+            name: String,
+            external_urls: ExternalUrls,
+        }
+        let h = Helper::deserialize(deserializer)?;
+        Ok(Self {
+            id: h.id,
+            name: h.name,
+            url: h.external_urls.spotify,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Paging<T> {
+    pub items: Vec<T>,
+    pub total: u32,
+    pub next: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SavedTrack {
+    pub track: TrackObject,
+}
+
+/// Tracks in playlists and the queue can also be episodes or local files.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaybeTrack {
+    Track(TrackObject),
+    Other(serde::de::IgnoredAny),
+}
+
+impl MaybeTrack {
+    fn track(self) -> Option<TrackObject> {
+        match self {
+            Self::Track(t) => Some(t),
+            Self::Other(_) => None,
+        }
+    }
+}
+
+pub struct PlaylistTrack {
+    /// `None` for episodes, local files and removed tracks.
+    pub track: Option<TrackObject>,
+}
+
+impl<'de> Deserialize<'de> for PlaylistTrack {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Helper {
+            track: MaybeTrack,
+        }
+        let h = Helper::deserialize(deserializer)?;
+        Ok(Self {
+            track: h.track.track(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PlayHistory {
+    pub track: TrackObject,
+    pub played_at: String,
+}
+
+/// Only the tracks of the queue, episodes are skipped.
+pub struct Queue {
+    pub currently_playing: Option<TrackObject>,
+    pub queue: Vec<TrackObject>,
+}
+
+impl<'de> Deserialize<'de> for Queue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Helper {
+            currently_playing: Option<MaybeTrack>,
+            queue: Vec<MaybeTrack>,
+        }
+        let h = Helper::deserialize(deserializer)?;
+        Ok(Self {
+            currently_playing: h.currently_playing.and_then(|t| t.track()),
+            queue: h.queue.into_iter().filter_map(|t| t.track()).collect(),
+        })
+    }
+}
+
+pub struct SimplifiedPlaylist {
+    pub id: PlaylistID,
+    pub name: String,
+    pub total_tracks: u32,
+}
+
+impl<'de> Deserialize<'de> for SimplifiedPlaylist {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Tracks {
+            total: u32,
+        }
+        #[derive(Deserialize)]
+        struct Helper {
+            id: PlaylistID,
+            name: String,
+            tracks: Tracks,
+        }
+        let h = Helper::deserialize(deserializer)?;
+        Ok(Self {
+            id: h.id,
+            name: h.name,
+            total_tracks: h.tracks.total,
         })
     }
 }
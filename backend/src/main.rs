@@ -1,6 +1,7 @@
 mod utility;
 mod what_anime;
 
+use anilist_api::AnilistAPIR;
 use anisong_api::AnisongAPIR;
 
 use database_api::DatabaseR;
@@ -18,9 +19,10 @@ async fn main() {
 
     dotenvy::from_path("../dev.env").expect("Environment load must succed");
     let database = DatabaseR::new(4).await;
-    let anisong = AnisongAPIR::new();
+    let anisong = AnisongAPIR::from_env();
     let spotify: SpotifyAPIR<20> = SpotifyAPIR::new();
-    let what_anime = WhatAnime::new(database, spotify, anisong);
+    let anilist = AnilistAPIR::new();
+    let what_anime = WhatAnime::new(database, spotify, anisong, anilist);
 
     what_anime.run().await;
 }
@@ -0,0 +1,107 @@
+//! Asks anisongdb for tracks the database misses, the import runs in the background so a
+//! later update picks up the hit.
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anilist_api::AnilistAPI;
+use anisong_api::AnisongAPI;
+use database_api::{Database, season_import::import_with_media};
+use log::{error, info};
+use spotify_api::{SpotifyAPI, models::TrackObject};
+use what_anime_shared::SpotifyTrackID;
+
+use super::routes::AppState;
+
+/// Misses are looked up again after this many seconds, anisongdb grows over time.
+const RETRY_AFTER: u64 = 60 * 60 * 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamLookup {
+    Running,
+    /// `imported` is true when the lookup added anisongs to the database.
+    Finished {
+        imported: bool,
+        at: u64,
+    },
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+/// True when a lookup for the track imported anisongs after `since`.
+pub fn imported_since<D, S, A, B>(
+    app_state: &AppState<D, S, A, B>,
+    id: &SpotifyTrackID,
+    since: u64,
+) -> bool
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    matches!(
+        app_state.upstream_lookups.lock().unwrap().get(id),
+        Some(UpstreamLookup::Finished { imported: true, at }) if *at >= since
+    )
+}
+
+/// Starts a lookup for the track unless one is running or finished recently.
+pub fn spawn_lookup<D, S, A, B>(app_state: Arc<AppState<D, S, A, B>>, track: &TrackObject)
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    {
+        let mut lookups = app_state.upstream_lookups.lock().unwrap();
+        let now = now();
+        lookups.retain(|_, l| match l {
+            UpstreamLookup::Running => true,
+            UpstreamLookup::Finished { at, .. } => now.saturating_sub(*at) < RETRY_AFTER,
+        });
+        if lookups.contains_key(&track.id) {
+            return;
+        }
+        lookups.insert(track.id.clone(), UpstreamLookup::Running);
+    }
+
+    let id = track.id.clone();
+    let name = track.name.clone();
+    let artists = track.artists.iter().map(|a| a.name.clone()).collect();
+    tokio::spawn(async move {
+        let imported = match app_state
+            .anisong_api
+            .full_search(name.clone(), artists)
+            .await
+        {
+            Ok(anisongs) if !anisongs.is_empty() => {
+                let summary =
+                    import_with_media(&app_state.database, &app_state.anilist_api, anisongs).await;
+                info!("Imported {:?} from anisongdb for {}", summary, name);
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                // Nothing was learned, so the next miss may try again.
+                error!("Upstream lookup for {} failed: {:?}", name, e);
+                app_state.upstream_lookups.lock().unwrap().remove(&id);
+                return;
+            }
+        };
+        app_state.upstream_lookups.lock().unwrap().insert(
+            id,
+            UpstreamLookup::Finished {
+                imported,
+                at: now(),
+            },
+        );
+    });
+}
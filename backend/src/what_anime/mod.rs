@@ -1,8 +1,10 @@
+mod fallback;
 mod models;
 mod routes;
+mod scan;
 mod utility;
 
-use anilist_api::AnilistAPIR;
+use anilist_api::AnilistAPI;
 use anisong_api::AnisongAPI;
 use axum::Router;
 use axum::http::HeaderValue;
@@ -16,8 +18,12 @@ use reqwest::header::ACCEPT;
 use reqwest::header::AUTHORIZATION;
 use routes::AppState;
 use routes::confirm_anime;
+use routes::create_playlist;
+use routes::more_by_artists;
 use routes::report;
+use routes::{anime, anime_songs, artist, history, isrc, my_stats, search_anime};
 use routes::{callback, login, update};
+use routes::{library_scan, recent_and_queued, start_library_scan};
 use spotify_api::SpotifyAPI;
 use spotify_api::models::ClientID;
 use spotify_api::models::ClientSecret;
@@ -29,15 +35,19 @@ use tower_sessions::MemoryStore;
 use tower_sessions::SessionManagerLayer;
 use tower_sessions::cookie;
 
-pub struct WhatAnime<D, S, A>
+pub struct WhatAnime<D, S, A, B>
 where
     D: Database + Send + Sync + 'static,
     S: SpotifyAPI + Send + Sync + 'static,
     A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
 {
-    app_state: Arc<AppState<D, S, A>>,
+    app_state: Arc<AppState<D, S, A, B>>,
 }
 
+/// Seasons refreshed by the periodic import, the current one included.
+const SEASON_REFRESH_WINDOW: u32 = 2;
+
 #[cfg(debug_assertions)]
 const FRONTEND_PORT: u16 = 5500; // Debug mode port
 #[cfg(debug_assertions)]
@@ -48,13 +58,14 @@ const FRONTEND_PORT: u16 = 5173; // Release mode port
 #[cfg(not(debug_assertions))]
 const BACKEND_PORT: u16 = 8000; // Release mode port
 
-impl<D, S, A> WhatAnime<D, S, A>
+impl<D, S, A, B> WhatAnime<D, S, A, B>
 where
     D: Database + Send + Sync + 'static,
     S: SpotifyAPI + Send + Sync + 'static,
     A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
 {
-    pub fn new(database: D, spotify_api: S, anisong_api: A) -> Self {
+    pub fn new(database: D, spotify_api: S, anisong_api: A, anilist_api: B) -> Self {
         let client_id =
             ClientID(std::env::var("client_id").expect("Environment variable client_id not set"));
         let client_secret = ClientSecret(
@@ -65,13 +76,16 @@ where
             app_state: Arc::new(AppState {
                 database,
                 spotify_api,
-                _anisong_api: anisong_api,
+                anisong_api,
                 client_id,
                 client_secret,
                 redirect_uri: Url::from_str(&format!(
                     "https://apiwhatanime.sibbeeegold.dev/callback"
                 ))
                 .expect("redirect must be valid str"),
+                library_scans: Default::default(),
+                anilist_api,
+                upstream_lookups: Default::default(),
             }),
         }
     }
@@ -92,15 +106,18 @@ where
             let interval_duration = tokio::time::Duration::from_secs(60 * 60); // 1 hour
             let mut interval = interval(interval_duration);
             let mut counter = 0;
-            let anilist = AnilistAPIR::new();
+            let refresh_window = std::env::var("season_refresh_window")
+                .map(|w| w.parse().expect("season_refresh_window must be a number"))
+                .unwrap_or(SEASON_REFRESH_WINDOW);
             loop {
                 counter += 1;
                 if counter == 12 {
                     counter = 0;
-                    let fetches = utility::update_current_season(
+                    let fetches = utility::update_recent_seasons(
                         &app_state_new.database,
-                        &app_state_new._anisong_api,
-                        &anilist,
+                        &app_state_new.anisong_api,
+                        &app_state_new.anilist_api,
+                        refresh_window,
                     )
                     .await;
                     info!("Fetched {} from anisong and updated data", fetches);
@@ -130,6 +147,17 @@ where
             .route("/callback", get(callback))
             .route("/confirm_anime", post(confirm_anime))
             .route("/report", post(report))
+            .route("/more_by_artists", post(more_by_artists))
+            .route("/anime", get(anime))
+            .route("/anime_songs", get(anime_songs))
+            .route("/search_anime", get(search_anime))
+            .route("/artist", get(artist))
+            .route("/isrc", get(isrc))
+            .route("/history", get(history))
+            .route("/me/stats", get(my_stats))
+            .route("/playlist", post(create_playlist))
+            .route("/recent_and_queued", get(recent_and_queued))
+            .route("/library_scan", get(library_scan).post(start_library_scan))
             .layer(session_layer)
             .layer(
                 CorsLayer::new()
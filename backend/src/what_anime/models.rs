@@ -1,7 +1,7 @@
-use database_api::models::DBAnisong;
+use database_api::models::{DBAnisong, Listen, PlaylistSong};
 use database_api::regex::process_possible_japanese;
 use serde::{Deserialize, Serialize};
-use spotify_api::models::TrackObject;
+use spotify_api::models::{Episode, LocalTrack, PlaybackState, Playlist, TrackObject};
 use what_anime_shared::{ImageURL, SpotifyTrackID};
 
 #[derive(Serialize, Deserialize)]
@@ -11,7 +11,34 @@ pub enum Update {
     LoginRequired,
     UnAuthorized,
     NotPlaying,
-    NewSong(SongUpdate),
+    PlayingEpisode(EpisodeInfo),
+    NewSong(Box<NowPlaying>),
+    /// Still the same track, only the play state changed.
+    Playback(Option<PlaybackState>),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NowPlaying {
+    #[serde(flatten)]
+    pub song: SongUpdate,
+    pub playback: Option<PlaybackState>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EpisodeInfo {
+    pub name: String,
+    pub show_name: String,
+    pub image: Option<ImageURL>,
+}
+
+impl EpisodeInfo {
+    pub fn from_episode(episode: &Episode) -> Self {
+        Self {
+            name: episode.name.clone(),
+            show_name: episode.show_name.clone(),
+            image: episode.image.clone(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -20,14 +47,36 @@ pub struct SongUpdate {
     pub anisongs: Anisongs,
 }
 
+impl SongUpdate {
+    pub fn to_listen(&self, track: &TrackObject) -> Listen {
+        let (certainty, best) = match &self.anisongs {
+            Anisongs::Hit(hit) => (Some(hit.certainty), hit.hits.first()),
+            Anisongs::Miss(_) => (None, None),
+        };
+        Listen {
+            track_id: track.id.clone(),
+            track_name: track.name.clone(),
+            artist_names: track.artists.iter().map(|a| a.name.clone()).collect(),
+            hit: best.is_some(),
+            certainty,
+            song_id: best.and_then(|b| b.song.id),
+            anime_ann_id: best.map(|b| b.anime.ann_id),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SongInfo {
     pub song_name: String,
     pub song_artists: Vec<String>,
     pub romanized_song_name: String,
     pub romanized_artists: Vec<String>,
-    pub album_image: ImageURL,
-    pub spotify_song_id: SpotifyTrackID,
+    pub album_name: Option<String>,
+    pub album_image: Option<ImageURL>,
+    pub duration_ms: u64,
+    /// `None` for local files.
+    pub spotify_song_id: Option<SpotifyTrackID>,
+    pub spotify_url: Option<String>,
 }
 
 impl SongInfo {
@@ -41,8 +90,29 @@ impl SongInfo {
                 .map(|a| process_possible_japanese(a))
                 .collect(),
             song_artists,
-            album_image: track.album.images[0].clone(),
-            spotify_song_id: track.id.clone(),
+            album_name: Some(track.album.name.clone()),
+            album_image: track.album.images.first().cloned(),
+            duration_ms: track.duration_ms,
+            spotify_song_id: Some(track.id.clone()),
+            spotify_url: track.url.clone(),
+        }
+    }
+
+    pub fn from_local(track: &LocalTrack) -> Self {
+        Self {
+            song_name: track.name.clone(),
+            romanized_song_name: process_possible_japanese(&track.name),
+            romanized_artists: track
+                .artists
+                .iter()
+                .map(|a| process_possible_japanese(a))
+                .collect(),
+            song_artists: track.artists.clone(),
+            album_name: track.album_name.clone(),
+            album_image: None,
+            duration_ms: track.duration_ms,
+            spotify_song_id: None,
+            spotify_url: None,
         }
     }
 }
@@ -58,6 +128,7 @@ pub enum Anisongs {
 pub struct NewSongHit {
     pub hits: Vec<DBAnisong>,
     pub more_by_artists: Vec<DBAnisong>,
+    pub more_by_artists_total: usize,
     pub certainty: i32,
 }
 
@@ -75,3 +146,25 @@ pub struct NewSongHit {
 pub struct NewSongMiss {
     pub possible: Vec<DBAnisong>,
 }
+
+#[derive(Serialize)]
+pub struct PlaylistReport {
+    /// `None` when none of the songs had a bound track, no playlist is created then.
+    pub playlist: Option<Playlist>,
+    pub added: usize,
+    pub unbound: Vec<PlaylistSong>,
+}
+
+#[derive(Serialize)]
+pub struct TrackMatch {
+    /// Only set for recently played tracks.
+    pub played_at: Option<String>,
+    #[serde(flatten)]
+    pub song: SongUpdate,
+}
+
+#[derive(Serialize)]
+pub struct RecentAndQueued {
+    pub recently_played: Vec<TrackMatch>,
+    pub queue: Vec<TrackMatch>,
+}
@@ -1,57 +1,77 @@
 use std::{
+    collections::{HashMap, HashSet},
     sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use anisong_api::{AnisongAPI, models::SongAnnId};
+use anilist_api::AnilistAPI;
+use anisong_api::{
+    AnisongAPI,
+    models::{AnisongArtistID, AnnAnimeID, SongAnnId},
+};
 use axum::{
     extract::{Query, State},
     response::{IntoResponse, Redirect},
 };
 use database_api::{
     Database,
-    models::{DBAnisong, Report},
+    models::{
+        AnimeLookup, ArtistLinkCandidate, DBAnisong, DBScanHit, DBUser, LibraryScan, Page, Paged,
+        PlaylistSong, PlaylistSource, Report,
+    },
 };
 use log::{error, info};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use spotify_api::{
     SpotifyAPI,
-    models::{ClientID, ClientSecret, CurrentlyPlaying, TokenResponse},
+    models::{
+        ClientID, ClientSecret, CurrentlyPlaying, LocalTrack, PlaybackState, SimplifiedArtist,
+        TokenResponse, TrackObject,
+    },
 };
 use tower_sessions::Session;
-use what_anime_shared::SpotifyTrackID;
+use what_anime_shared::{
+    AnilistAnimeID, Isrc, SongID, SpotifyArtistID, SpotifyTrackID, SpotifyUserID,
+};
 
 use crate::what_anime::utility::select_best;
 
 use super::{
     FRONTEND_PORT,
+    fallback::{self, UpstreamLookup},
     models::{self, NewSongHit, NewSongMiss, SongInfo, SongUpdate},
+    scan,
     utility::{pair_artists, select_best_by_song_title},
 };
 
-pub struct AppState<D, S, A>
+pub struct AppState<D, S, A, B>
 where
     D: Database + Send + Sync + 'static,
     S: SpotifyAPI + Send + Sync + 'static,
     A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
 {
     pub database: D,
     pub spotify_api: S,
-    pub _anisong_api: A,
+    pub anisong_api: A,
     pub client_id: ClientID,
     pub client_secret: ClientSecret,
     pub redirect_uri: Url,
+    pub library_scans: std::sync::Mutex<HashSet<SpotifyUserID>>,
+    pub anilist_api: B,
+    pub upstream_lookups: std::sync::Mutex<HashMap<SpotifyTrackID, UpstreamLookup>>,
 }
 
-pub async fn login<D, S, A>(
-    State(app_state): State<Arc<AppState<D, S, A>>>,
+pub async fn login<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
     session: Session,
 ) -> impl IntoResponse
 where
     D: Database + Send + Sync + 'static,
     S: SpotifyAPI + Send + Sync + 'static,
     A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
 {
     let (state, url) = app_state
         .spotify_api
@@ -68,9 +88,13 @@ pub struct UpdateParams {
 }
 
 const AUTO_BIND_LIMIT: f32 = 80.0;
+/// Pairs scoring below this are unrelated artists, not worth keeping as candidates.
+const MIN_CANDIDATE_CONFIDENCE: f32 = 50.0;
+const MORE_BY_ARTISTS_LIMIT: usize = 25;
+const MAX_PAGE_SIZE: i64 = 100;
 
-pub async fn update<D, S, A>(
-    State(app_state): State<Arc<AppState<D, S, A>>>,
+pub async fn update<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
     session: Session,
     Query(params): Query<UpdateParams>,
 ) -> impl IntoResponse
@@ -78,6 +102,7 @@ where
     D: Database + Send + Sync + 'static,
     S: SpotifyAPI + Send + Sync + 'static,
     A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
 {
     let token = get_token_data(
         session.clone(),
@@ -94,152 +119,74 @@ where
     };
 
     match app_state.spotify_api.get_current(token.access_token).await {
-        Ok(p) => match p {
+        Ok((p, playback)) => match p {
             CurrentlyPlaying::Track(t) => {
-                if params.refresh != Some(true) {
-                    let prev_played = get_prev_played(session.clone()).await.unwrap();
-                    if prev_played.as_ref() == Some(&t.id) {
-                        return axum::Json(models::Update::NoUpdates);
-                    }
+                let prev_played = get_prev_played(session.clone()).await.unwrap();
+                let is_new = prev_played.as_ref() != Some(&t.id);
+                let toggled = toggled_playing(session.clone(), playback.as_ref()).await;
+                let prev_miss_at: Option<u64> = session.get("prev_miss_at").await.unwrap();
+                let upstream_hit = !is_new
+                    && prev_miss_at.is_some_and(|m| fallback::imported_since(&app_state, &t.id, m));
+                if !is_new && !upstream_hit && params.refresh != Some(true) {
+                    return axum::Json(if toggled {
+                        models::Update::Playback(playback)
+                    } else {
+                        models::Update::NoUpdates
+                    });
                 }
                 insert_prev_played(session.clone(), t.id.clone())
                     .await
                     .unwrap();
 
-                let anisongs = app_state
-                    .database
-                    .get_anisongs_by_song_id(t.id.clone())
-                    .await;
-                if !anisongs.is_empty() {
-                    let hit_id = anisongs[0]
-                        .song
-                        .id
-                        .expect("anisong from database should always contain an id");
-                    let (hits, more_by_artists): (Vec<DBAnisong>, Vec<DBAnisong>) = anisongs
-                        .into_iter()
-                        .partition(|a| a.song.id == Some(hit_id));
-
-                    let artist_pairs =
-                        pair_artists(t.artists.clone(), hits[0].song.artists.clone());
-                    let artist_binds = artist_pairs
-                        .into_iter()
-                        .filter_map(|a| {
-                            if a.2 > AUTO_BIND_LIMIT {
-                                Some((a.1.id, a.0.id))
-                            } else {
-                                None
-                            }
-                        })
-                        .collect();
-                    app_state.database.bind_artists(artist_binds).await;
-
-                    return axum::Json(models::Update::NewSong(SongUpdate {
-                        song_info: SongInfo::from_track(&t),
-                        anisongs: models::Anisongs::Hit(NewSongHit {
-                            hits,
-                            more_by_artists,
-                            certainty: 100,
-                        }),
-                    }));
-                }
-                let anisongs = app_state
-                    .database
-                    .get_anisongs_by_artist_ids(t.artists.iter().map(|a| a.id.clone()).collect())
-                    .await;
-
-                if !anisongs.is_empty() {
-                    let mut song = select_best_by_song_title(anisongs, &t.name);
-                    if song.certainty >= AUTO_BIND_LIMIT as i32 {
-                        song.certainty = 100;
-                        let artist_binds =
-                            pair_artists(t.artists.clone(), song.hits[0].song.artists.clone())
-                                .into_iter()
-                                .filter_map(|a| {
-                                    if a.2 > AUTO_BIND_LIMIT {
-                                        Some((a.1.id, a.0.id))
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .collect();
-                        app_state.database.bind_artists(artist_binds).await;
-                        let best_id = song.hits[0].song.id.expect("From database must be Some");
-                        app_state
-                            .database
-                            .bind_songs(vec![(best_id, t.id.clone())])
-                            .await;
-                    }
-                    return axum::Json(models::Update::NewSong(SongUpdate {
-                        song_info: SongInfo::from_track(&t),
-                        anisongs: models::Anisongs::Hit(song),
-                    }));
+                let song_update = identify_track(&app_state.database, &t, true).await;
+                if let models::Anisongs::Miss(_) = song_update.anisongs {
+                    session
+                        .insert("prev_miss_at", fallback::now())
+                        .await
+                        .unwrap();
+                    fallback::spawn_lookup(app_state.clone(), &t);
+                } else {
+                    session.remove::<u64>("prev_miss_at").await.unwrap();
                 }
-                let anisongs = app_state
-                    .database
-                    .full_search(
-                        t.name.clone(),
-                        t.artists.iter().map(|a| a.name.clone()).collect(),
-                        true,
-                        true,
-                    )
-                    .await;
-                if !anisongs.is_empty() {
-                    let (mut song, artist_pairs) =
-                        select_best(anisongs, t.name.clone(), t.artists.clone());
-
-                    let final_search_ids = song.hits[0].song.artists.iter().map(|a| a.id).collect();
-                    let hit_song_id = song.hits[0].song.id.expect("must be some");
-                    if song.certainty >= AUTO_BIND_LIMIT as i32 {
-                        song.certainty = 100;
-                        let artist_binds = artist_pairs
-                            .into_iter()
-                            .filter_map(|a| {
-                                if a.2 > AUTO_BIND_LIMIT {
-                                    Some((a.1.id, a.0.id))
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect();
-                        app_state.database.bind_artists(artist_binds).await;
-                        let best_id = song.hits[0].song.id.expect("From database must be Some");
-                        app_state
-                            .database
-                            .bind_songs(vec![(best_id, t.id.clone())])
-                            .await;
-                    }
-                    let all_songs = app_state
+                if is_new && let Ok(Some(user)) = session.get::<DBUser>("user").await {
+                    app_state
                         .database
-                        .get_anisongs_by_ani_artist_ids(final_search_ids)
+                        .add_listen(user.id, song_update.to_listen(&t))
                         .await;
-
-                    let (hits, more) = all_songs
-                        .into_iter()
-                        .partition(|a| a.song.id == Some(hit_song_id));
-
-                    song.hits = hits;
-                    song.more_by_artists = more;
-                    return axum::Json(models::Update::NewSong(SongUpdate {
-                        song_info: SongInfo::from_track(&t),
-                        anisongs: models::Anisongs::Hit(song),
-                    }));
                 }
-                let possible = app_state
-                    .database
-                    .full_search(
-                        t.name.clone(),
-                        t.artists.iter().map(|a| a.name.clone()).collect(),
-                        false,
-                        false,
-                    )
-                    .await;
-
-                return axum::Json(models::Update::NewSong(SongUpdate {
-                    song_info: SongInfo::from_track(&t),
-                    anisongs: models::Anisongs::Miss(NewSongMiss { possible }),
-                }));
+                axum::Json(models::Update::NewSong(Box::new(models::NowPlaying {
+                    song: song_update,
+                    playback,
+                })))
+            }
+            CurrentlyPlaying::LocalTrack(t) => {
+                let local_id = SpotifyTrackID(t.uri.clone());
+                let prev_played = get_prev_played(session.clone()).await.unwrap();
+                let toggled = toggled_playing(session.clone(), playback.as_ref()).await;
+                if prev_played.as_ref() == Some(&local_id) && params.refresh != Some(true) {
+                    return axum::Json(if toggled {
+                        models::Update::Playback(playback)
+                    } else {
+                        models::Update::NoUpdates
+                    });
+                }
+                insert_prev_played(session.clone(), local_id).await.unwrap();
+
+                let song_update = identify_local_track(&app_state.database, &t).await;
+                axum::Json(models::Update::NewSong(Box::new(models::NowPlaying {
+                    song: song_update,
+                    playback,
+                })))
+            }
+            CurrentlyPlaying::Episode(e) => {
+                insert_prev_played(session.clone(), SpotifyTrackID("".to_string()))
+                    .await
+                    .unwrap();
+                axum::Json(models::Update::PlayingEpisode(
+                    models::EpisodeInfo::from_episode(&e),
+                ))
             }
-            _ => {
+            CurrentlyPlaying::Nothing => {
                 insert_prev_played(session.clone(), SpotifyTrackID("".to_string()))
                     .await
                     .unwrap();
@@ -250,21 +197,293 @@ where
     }
 }
 
+/// Pairs the artists of a confirmed bind, including the groups and members of the performers
+/// since spotify often credits those instead. Pairs between the candidate floor and the auto bind
+/// limit become candidates.
+async fn propagate_artist_links<D: Database>(database: &D, track: &TrackObject, song_id: SongID) {
+    let song = database
+        .get_anisongs_by_song_id(track.id.clone(), None)
+        .await
+        .items
+        .into_iter()
+        .find(|a| a.song.id == Some(song_id));
+    let Some(song) = song else {
+        return;
+    };
+    let mut artists = song.song.artists;
+    let related_ids: Vec<AnisongArtistID> = artists
+        .iter()
+        .flat_map(|a| a.group_ids.iter().chain(a.member_ids.iter()).copied())
+        .filter(|id| !artists.iter().any(|a| a.id == *id))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    artists.extend(database.get_artists(related_ids).await);
+
+    let (binds, candidates): (Vec<_>, Vec<_>) = pair_artists(track.artists.clone(), artists)
+        .into_iter()
+        .partition(|a| a.2 > AUTO_BIND_LIMIT);
+    let bound = database
+        .bind_artists(binds.into_iter().map(|a| (a.1.id, a.0.id)).collect())
+        .await;
+    let candidates = database
+        .add_artist_link_candidates(
+            candidates
+                .into_iter()
+                .filter(|a| a.2 >= MIN_CANDIDATE_CONFIDENCE)
+                .map(|a| ArtistLinkCandidate {
+                    artist_id: a.1.id,
+                    spotify_id: a.0.id,
+                    confidence: a.2,
+                    song_id,
+                })
+                .collect(),
+        )
+        .await;
+    info!(
+        "Propagated {} artist links and {} candidates from {}",
+        bound, candidates, track.id
+    );
+}
+
+/// Matches a Spotify track against the database, `auto_bind` stores the confident matches.
+pub(super) async fn identify_track<D: Database>(
+    database: &D,
+    track: &TrackObject,
+    auto_bind: bool,
+) -> SongUpdate {
+    let mut anisongs = database
+        .get_anisongs_by_song_id(track.id.clone(), None)
+        .await
+        .items;
+    // Another release of the same recording may already be bound.
+    if anisongs.is_empty()
+        && let Some(isrc) = track.isrc.clone()
+    {
+        anisongs = database.get_anisongs_by_isrc(isrc, None).await.items;
+    }
+    if !anisongs.is_empty() {
+        let hit_id = anisongs[0]
+            .song
+            .id
+            .expect("anisong from database should always contain an id");
+        // Links the track itself and fills in the ISRC of links made before it was stored.
+        if auto_bind && track.isrc.is_some() {
+            database
+                .bind_songs(vec![(hit_id, track.id.clone(), track.isrc.clone())])
+                .await;
+        }
+        let hits: Vec<DBAnisong> = anisongs
+            .into_iter()
+            .filter(|a| a.song.id == Some(hit_id))
+            .collect();
+
+        let artist_pairs = pair_artists(track.artists.clone(), hits[0].song.artists.clone());
+        let artist_binds = artist_pairs
+            .into_iter()
+            .filter_map(|a| {
+                if a.2 > AUTO_BIND_LIMIT {
+                    Some((a.1.id, a.0.id))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if auto_bind {
+            database.bind_artists(artist_binds).await;
+        }
+
+        let mut song = NewSongHit {
+            hits,
+            more_by_artists: vec![],
+            more_by_artists_total: 0,
+            certainty: 100,
+        };
+        fill_more_by_artists(database, &mut song).await;
+        return SongUpdate {
+            song_info: SongInfo::from_track(track),
+            anisongs: models::Anisongs::Hit(song),
+        };
+    }
+    let anisongs = database
+        .get_anisongs_by_artist_ids(track.artists.iter().map(|a| a.id.clone()).collect(), None)
+        .await
+        .items;
+
+    if !anisongs.is_empty() {
+        let mut song = select_best_by_song_title(anisongs, &track.name);
+        if song.certainty >= AUTO_BIND_LIMIT as i32 {
+            song.certainty = 100;
+        }
+        if song.certainty == 100 && auto_bind {
+            let artist_binds =
+                pair_artists(track.artists.clone(), song.hits[0].song.artists.clone())
+                    .into_iter()
+                    .filter_map(|a| {
+                        if a.2 > AUTO_BIND_LIMIT {
+                            Some((a.1.id, a.0.id))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+            database.bind_artists(artist_binds).await;
+            let best_id = song.hits[0].song.id.expect("From database must be Some");
+            database
+                .bind_songs(vec![(best_id, track.id.clone(), track.isrc.clone())])
+                .await;
+        }
+        fill_more_by_artists(database, &mut song).await;
+        return SongUpdate {
+            song_info: SongInfo::from_track(track),
+            anisongs: models::Anisongs::Hit(song),
+        };
+    }
+    let anisongs = database
+        .full_search(
+            track.name.clone(),
+            track.artists.iter().map(|a| a.name.clone()).collect(),
+            true,
+            true,
+            None,
+        )
+        .await
+        .items;
+    if !anisongs.is_empty() {
+        let (mut song, artist_pairs) =
+            select_best(anisongs, track.name.clone(), track.artists.clone());
+
+        let hit_song_id = song.hits[0].song.id.expect("must be some");
+        if song.certainty >= AUTO_BIND_LIMIT as i32 {
+            song.certainty = 100;
+        }
+        if song.certainty == 100 && auto_bind {
+            let artist_binds = artist_pairs
+                .into_iter()
+                .filter_map(|a| {
+                    if a.2 > AUTO_BIND_LIMIT {
+                        Some((a.1.id, a.0.id))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            database.bind_artists(artist_binds).await;
+            let best_id = song.hits[0].song.id.expect("From database must be Some");
+            database
+                .bind_songs(vec![(best_id, track.id.clone(), track.isrc.clone())])
+                .await;
+        }
+        // Equal scores can tie other songs, only the binds of the chosen one are hits.
+        song.hits.retain(|a| a.song.id == Some(hit_song_id));
+        fill_more_by_artists(database, &mut song).await;
+        return SongUpdate {
+            song_info: SongInfo::from_track(track),
+            anisongs: models::Anisongs::Hit(song),
+        };
+    }
+    let possible = database
+        .full_search(
+            track.name.clone(),
+            track.artists.iter().map(|a| a.name.clone()).collect(),
+            false,
+            false,
+            Some(Page {
+                limit: MORE_BY_ARTISTS_LIMIT as i64,
+                offset: 0,
+            }),
+        )
+        .await
+        .items;
+
+    SongUpdate {
+        song_info: SongInfo::from_track(track),
+        anisongs: models::Anisongs::Miss(NewSongMiss { possible }),
+    }
+}
+
+/// Replaces `more_by_artists` with the first page of other songs by the artists of the hit, the
+/// following pages come from `/more_by_artists` with the same artist and excluded song ids.
+async fn fill_more_by_artists<D: Database>(database: &D, song: &mut NewSongHit) {
+    let Some(hit) = song.hits.first() else {
+        return;
+    };
+    let artist_ids = hit.song.artists.iter().map(|a| a.id).collect();
+    let mut hit_ids: Vec<SongID> = song.hits.iter().filter_map(|h| h.song.id).collect();
+    hit_ids.dedup();
+    let more = database
+        .get_anisongs_by_ani_artist_ids(
+            artist_ids,
+            hit_ids,
+            Some(Page {
+                limit: MORE_BY_ARTISTS_LIMIT as i64,
+                offset: 0,
+            }),
+        )
+        .await;
+    song.more_by_artists = more.items;
+    song.more_by_artists_total = more.total as usize;
+}
+
+/// Local files have no spotify ids to bind, so they are only matched by name and artists.
+async fn identify_local_track<D: Database>(database: &D, track: &LocalTrack) -> SongUpdate {
+    let anisongs = database
+        .full_search(track.name.clone(), track.artists.clone(), true, true, None)
+        .await
+        .items;
+    if !anisongs.is_empty() {
+        // The names stand in for the missing artist ids, pairs are only used for scoring here
+        let artists = track
+            .artists
+            .iter()
+            .map(|name| SimplifiedArtist {
+                id: SpotifyArtistID(format!("local:{}", name)),
+                name: name.clone(),
+            })
+            .collect();
+        let (mut song, _) = select_best(anisongs, track.name.clone(), artists);
+        fill_more_by_artists(database, &mut song).await;
+        return SongUpdate {
+            song_info: SongInfo::from_local(track),
+            anisongs: models::Anisongs::Hit(song),
+        };
+    }
+    let possible = database
+        .full_search(
+            track.name.clone(),
+            track.artists.clone(),
+            false,
+            false,
+            Some(Page {
+                limit: MORE_BY_ARTISTS_LIMIT as i64,
+                offset: 0,
+            }),
+        )
+        .await
+        .items;
+
+    SongUpdate {
+        song_info: SongInfo::from_local(track),
+        anisongs: models::Anisongs::Miss(NewSongMiss { possible }),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct CallbackParams {
     code: String,
     state: spotify_api::models::State,
 }
 
-pub async fn callback<D, S, A>(
+pub async fn callback<D, S, A, B>(
     Query(params): Query<CallbackParams>,
-    State(app_state): State<Arc<AppState<D, S, A>>>,
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
     session: Session,
 ) -> impl IntoResponse
 where
     D: Database + Send + Sync + 'static,
     S: SpotifyAPI + Send + Sync + 'static,
     A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
 {
     session.load().await.unwrap();
 
@@ -345,8 +564,8 @@ pub struct ConfirmationParams {
     pub spotify_song_id: what_anime_shared::SpotifyTrackID,
 }
 
-pub async fn confirm_anime<D, S, A>(
-    State(app_state): State<Arc<AppState<D, S, A>>>,
+pub async fn confirm_anime<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
     session: Session,
     axum::Json(params): axum::Json<ConfirmationParams>,
 ) -> impl IntoResponse
@@ -354,6 +573,7 @@ where
     D: Database + Send + Sync + 'static,
     S: SpotifyAPI + Send + Sync + 'static,
     A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
 {
     let token = get_token_data(
         session,
@@ -363,20 +583,587 @@ where
     )
     .await;
     if let Ok(Some(token)) = token {
-        let user = app_state.spotify_api.get_user(token.access_token).await;
+        let user = app_state
+            .spotify_api
+            .get_user(token.access_token.clone())
+            .await;
         if let Ok(user) = user {
             info!(
                 "{:?} added bind for {:?}\nhttps://open.spotify.com/track/{}",
                 user.display_name, params.song_id, params.spotify_song_id
             );
+            let track = app_state
+                .spotify_api
+                .get_song(token.access_token, params.spotify_song_id.clone())
+                .await
+                .ok();
+            let isrc = track.as_ref().and_then(|t| t.isrc.clone());
             app_state
                 .database
-                .bind_songs(vec![(params.song_id, params.spotify_song_id)])
+                .bind_songs(vec![(params.song_id, params.spotify_song_id, isrc)])
                 .await;
+            if let Some(track) = track {
+                propagate_artist_links(&app_state.database, &track, params.song_id).await;
+            }
         }
     }
 }
 
+#[derive(Deserialize)]
+pub struct MoreByArtistsParams {
+    pub artist_ids: Vec<AnisongArtistID>,
+    /// The hits the list was shown for, see `fill_more_by_artists`.
+    pub exclude_song_ids: Option<Vec<SongID>>,
+    pub offset: i64,
+    pub limit: Option<i64>,
+}
+
+pub async fn more_by_artists<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    axum::Json(params): axum::Json<MoreByArtistsParams>,
+) -> impl IntoResponse
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let page = Page {
+        limit: params
+            .limit
+            .unwrap_or(MORE_BY_ARTISTS_LIMIT as i64)
+            .clamp(1, MAX_PAGE_SIZE),
+        offset: params.offset.max(0),
+    };
+    axum::Json(
+        app_state
+            .database
+            .get_anisongs_by_ani_artist_ids(
+                params.artist_ids,
+                params.exclude_song_ids.unwrap_or_default(),
+                Some(page),
+            )
+            .await,
+    )
+}
+
+#[derive(Deserialize)]
+pub struct AnimeParams {
+    pub ann_id: Option<AnnAnimeID>,
+    pub anilist_id: Option<AnilistAnimeID>,
+}
+
+pub async fn anime<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    Query(params): Query<AnimeParams>,
+) -> Result<impl IntoResponse, axum::http::StatusCode>
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let lookup = match (params.ann_id, params.anilist_id) {
+        (Some(id), _) => AnimeLookup::AnnId(id),
+        (None, Some(id)) => AnimeLookup::AnilistId(id),
+        (None, None) => return Err(axum::http::StatusCode::BAD_REQUEST),
+    };
+    match app_state.database.get_anime(lookup).await {
+        Some(anime) => Ok(axum::Json(anime)),
+        None => Err(axum::http::StatusCode::NOT_FOUND),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AnimeSongsParams {
+    pub ann_id: AnnAnimeID,
+}
+
+pub async fn anime_songs<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    Query(params): Query<AnimeSongsParams>,
+) -> impl IntoResponse
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    axum::Json(app_state.database.get_songs_for_anime(params.ann_id).await)
+}
+
+#[derive(Deserialize)]
+pub struct IsrcParams {
+    pub isrc: Isrc,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+pub async fn isrc<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    Query(params): Query<IsrcParams>,
+) -> impl IntoResponse
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let page = Page {
+        limit: params
+            .limit
+            .unwrap_or(MAX_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+        offset: params.offset.unwrap_or(0).max(0),
+    };
+    axum::Json(
+        app_state
+            .database
+            .get_anisongs_by_isrc(params.isrc, Some(page))
+            .await,
+    )
+}
+
+#[derive(Deserialize)]
+pub struct SearchAnimeParams {
+    pub query: String,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+pub async fn search_anime<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    Query(params): Query<SearchAnimeParams>,
+) -> Result<impl IntoResponse, axum::http::StatusCode>
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    if params.query.trim().is_empty() {
+        return Err(axum::http::StatusCode::BAD_REQUEST);
+    }
+    let page = Page {
+        limit: params
+            .limit
+            .unwrap_or(MAX_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+        offset: params.offset.unwrap_or(0).max(0),
+    };
+    Ok(axum::Json(
+        app_state
+            .database
+            .search_anime(params.query.trim().to_string(), page)
+            .await,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct ArtistParams {
+    pub artist_id: AnisongArtistID,
+}
+
+pub async fn artist<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    Query(params): Query<ArtistParams>,
+) -> Result<impl IntoResponse, axum::http::StatusCode>
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    match app_state.database.get_artist(params.artist_id).await {
+        Some(artist) => Ok(axum::Json(artist)),
+        None => Err(axum::http::StatusCode::NOT_FOUND),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct HistoryParams {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+    pub hits_only: Option<bool>,
+}
+
+pub async fn history<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    session: Session,
+    Query(params): Query<HistoryParams>,
+) -> Result<impl IntoResponse, axum::http::StatusCode>
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let user = match session.get::<DBUser>("user").await {
+        Ok(Some(u)) => u,
+        _ => return Err(axum::http::StatusCode::UNAUTHORIZED),
+    };
+    let page = Page {
+        limit: params
+            .limit
+            .unwrap_or(MAX_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+        offset: params.offset.unwrap_or(0).max(0),
+    };
+    Ok(axum::Json(
+        app_state
+            .database
+            .get_listens(user.id, params.hits_only.unwrap_or(false), page)
+            .await,
+    ))
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsWindow {
+    Week,
+    Month,
+    Year,
+    #[default]
+    All,
+}
+
+impl StatsWindow {
+    fn since(self) -> Option<chrono::DateTime<chrono::Utc>> {
+        let days = match self {
+            Self::Week => 7,
+            Self::Month => 30,
+            Self::Year => 365,
+            Self::All => return None,
+        };
+        Some(chrono::Utc::now() - chrono::Duration::days(days))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct StatsParams {
+    pub window: Option<StatsWindow>,
+    pub limit: Option<i64>,
+}
+
+const STATS_LIMIT: i64 = 10;
+
+pub async fn my_stats<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    session: Session,
+    Query(params): Query<StatsParams>,
+) -> Result<impl IntoResponse, axum::http::StatusCode>
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let user = match session.get::<DBUser>("user").await {
+        Ok(Some(u)) => u,
+        _ => return Err(axum::http::StatusCode::UNAUTHORIZED),
+    };
+    let since = params.window.unwrap_or_default().since();
+    let limit = params.limit.unwrap_or(STATS_LIMIT).clamp(1, MAX_PAGE_SIZE);
+    Ok(axum::Json(
+        app_state
+            .database
+            .get_user_stats(user.id, since, limit)
+            .await,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct PlaylistParams {
+    pub source: PlaylistSource,
+    pub name: String,
+    pub public: Option<bool>,
+}
+
+pub async fn create_playlist<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    session: Session,
+    axum::Json(params): axum::Json<PlaylistParams>,
+) -> Result<impl IntoResponse, axum::http::StatusCode>
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let token = match get_token_data(
+        session.clone(),
+        &app_state.spotify_api,
+        app_state.client_id.clone(),
+        app_state.client_secret.clone(),
+    )
+    .await
+    {
+        Ok(Some(v)) => v,
+        _ => return Err(axum::http::StatusCode::UNAUTHORIZED),
+    };
+    let user = match app_state
+        .spotify_api
+        .get_user(token.access_token.clone())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let (bound, unbound): (Vec<PlaylistSong>, Vec<PlaylistSong>) = app_state
+        .database
+        .get_playlist_songs(params.source)
+        .await
+        .into_iter()
+        .partition(|s| s.spotify_id.is_some());
+
+    let mut seen = HashSet::new();
+    let tracks: Vec<SpotifyTrackID> = bound
+        .into_iter()
+        .filter_map(|s| s.spotify_id)
+        .filter(|t| seen.insert(t.clone()))
+        .collect();
+
+    if tracks.is_empty() {
+        return Ok(axum::Json(models::PlaylistReport {
+            playlist: None,
+            added: 0,
+            unbound,
+        }));
+    }
+
+    let playlist = match app_state
+        .spotify_api
+        .create_playlist(
+            token.access_token.clone(),
+            user.id,
+            params.name,
+            "Made with WhatAnime".to_string(),
+            params.public.unwrap_or(false),
+        )
+        .await
+    {
+        Ok(p) => p,
+        Err(e) => {
+            error!("Failed to create playlist: {:?}", e);
+            return Err(axum::http::StatusCode::BAD_GATEWAY);
+        }
+    };
+    let added = tracks.len();
+    if let Err(e) = app_state
+        .spotify_api
+        .add_tracks(token.access_token, playlist.id.clone(), tracks)
+        .await
+    {
+        error!("Failed to add tracks to playlist {}: {:?}", playlist.id, e);
+        return Err(axum::http::StatusCode::BAD_GATEWAY);
+    }
+
+    Ok(axum::Json(models::PlaylistReport {
+        playlist: Some(playlist),
+        added,
+        unbound,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct RecentAndQueuedParams {
+    pub played: Option<u32>,
+    pub queued: Option<u32>,
+}
+
+const RECENT_AND_QUEUED_DEFAULT: u32 = 5;
+const RECENT_AND_QUEUED_MAX: u32 = 20;
+
+pub async fn recent_and_queued<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    session: Session,
+    Query(params): Query<RecentAndQueuedParams>,
+) -> Result<impl IntoResponse, axum::http::StatusCode>
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let token = match get_token_data(
+        session,
+        &app_state.spotify_api,
+        app_state.client_id.clone(),
+        app_state.client_secret.clone(),
+    )
+    .await
+    {
+        Ok(Some(v)) => v,
+        _ => return Err(axum::http::StatusCode::UNAUTHORIZED),
+    };
+    let played = params
+        .played
+        .unwrap_or(RECENT_AND_QUEUED_DEFAULT)
+        .min(RECENT_AND_QUEUED_MAX);
+    let queued = params
+        .queued
+        .unwrap_or(RECENT_AND_QUEUED_DEFAULT)
+        .min(RECENT_AND_QUEUED_MAX) as usize;
+
+    let history = if played == 0 {
+        vec![]
+    } else {
+        match app_state
+            .spotify_api
+            .get_recently_played(token.access_token.clone(), played)
+            .await
+        {
+            Ok(h) => h,
+            Err(e) => {
+                error!("Failed to fetch recently played: {:?}", e);
+                return Err(axum::http::StatusCode::BAD_GATEWAY);
+            }
+        }
+    };
+    let queue = if queued == 0 {
+        vec![]
+    } else {
+        match app_state.spotify_api.get_queue(token.access_token).await {
+            Ok(q) => q.queue,
+            Err(e) => {
+                error!("Failed to fetch queue: {:?}", e);
+                return Err(axum::http::StatusCode::BAD_GATEWAY);
+            }
+        }
+    };
+
+    let mut recently_played = Vec::with_capacity(history.len());
+    for played in history {
+        recently_played.push(models::TrackMatch {
+            song: identify_track(&app_state.database, &played.track, false).await,
+            played_at: Some(played.played_at),
+        });
+    }
+    let mut upcoming = Vec::with_capacity(queued);
+    for track in queue.iter().take(queued) {
+        upcoming.push(models::TrackMatch {
+            song: identify_track(&app_state.database, track, false).await,
+            played_at: None,
+        });
+    }
+
+    Ok(axum::Json(models::RecentAndQueued {
+        recently_played,
+        queue: upcoming,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct StartScanParams {
+    pub auto_bind: Option<bool>,
+    pub restart: Option<bool>,
+}
+
+pub async fn start_library_scan<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    session: Session,
+    axum::Json(params): axum::Json<StartScanParams>,
+) -> Result<impl IntoResponse, axum::http::StatusCode>
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let token = match get_token_data(
+        session.clone(),
+        &app_state.spotify_api,
+        app_state.client_id.clone(),
+        app_state.client_secret.clone(),
+    )
+    .await
+    {
+        Ok(Some(v)) => v,
+        _ => return Err(axum::http::StatusCode::UNAUTHORIZED),
+    };
+    let user = match session.get::<DBUser>("user").await {
+        Ok(Some(u)) => u,
+        _ => return Err(axum::http::StatusCode::UNAUTHORIZED),
+    };
+
+    if !app_state
+        .library_scans
+        .lock()
+        .unwrap()
+        .insert(user.id.clone())
+    {
+        return Err(axum::http::StatusCode::CONFLICT);
+    }
+
+    let scan = match app_state.database.get_library_scan(user.id.clone()).await {
+        Some(mut scan) if !scan.finished && params.restart != Some(true) => {
+            scan.auto_bind = params.auto_bind.unwrap_or(scan.auto_bind);
+            scan
+        }
+        _ => {
+            app_state
+                .database
+                .start_library_scan(user.id, params.auto_bind.unwrap_or(false))
+                .await
+        }
+    };
+
+    info!("Starting library scan for {:?}", user.name);
+    tokio::spawn(scan::run_library_scan(
+        app_state.clone(),
+        token,
+        scan.clone(),
+    ));
+    Ok(axum::Json(scan))
+}
+
+#[derive(Deserialize)]
+pub struct ScanReportParams {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ScanReport {
+    pub scan: Option<LibraryScan>,
+    pub running: bool,
+    pub hits: Paged<DBScanHit>,
+}
+
+pub async fn library_scan<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
+    session: Session,
+    Query(params): Query<ScanReportParams>,
+) -> Result<impl IntoResponse, axum::http::StatusCode>
+where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let user = match session.get::<DBUser>("user").await {
+        Ok(Some(u)) => u,
+        _ => return Err(axum::http::StatusCode::UNAUTHORIZED),
+    };
+    let page = Page {
+        limit: params
+            .limit
+            .unwrap_or(MAX_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+        offset: params.offset.unwrap_or(0).max(0),
+    };
+    let running = app_state.library_scans.lock().unwrap().contains(&user.id);
+    Ok(axum::Json(ScanReport {
+        scan: app_state.database.get_library_scan(user.id.clone()).await,
+        running,
+        hits: app_state
+            .database
+            .get_library_scan_hits(user.id, page)
+            .await,
+    }))
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct ReportParams {
     pub track_id: Option<SpotifyTrackID>,
@@ -384,8 +1171,8 @@ pub struct ReportParams {
     pub message: String,
 }
 
-pub async fn report<D, S, A>(
-    State(app_state): State<Arc<AppState<D, S, A>>>,
+pub async fn report<D, S, A, B>(
+    State(app_state): State<Arc<AppState<D, S, A, B>>>,
     session: Session,
     axum::Json(params): axum::Json<ReportParams>,
 ) -> Result<impl IntoResponse, axum::http::StatusCode>
@@ -393,6 +1180,7 @@ where
     D: Database + Send + Sync + 'static,
     S: SpotifyAPI + Send + Sync + 'static,
     A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
 {
     let token_data = match get_token_data(
         session.clone(),
@@ -451,6 +1239,13 @@ async fn get_token_data<T: SpotifyAPI>(
     client_secret: ClientSecret,
 ) -> Result<Option<TokenResponse>, tower_sessions::session::Error> {
     let token = session.get::<TokenResponse>("token").await?;
+    // Logins from before a scope was added have to go through login again.
+    if token
+        .as_ref()
+        .is_some_and(|t| !t.has_scopes(spotify_api::SCOPES))
+    {
+        return Ok(None);
+    }
     if let Some(t) = token.as_ref() {
         if t.expires_in
             < SystemTime::now()
@@ -485,3 +1280,10 @@ async fn get_prev_played(
 ) -> Result<Option<SpotifyTrackID>, tower_sessions::session::Error> {
     session.get("prev_played").await
 }
+/// Remembers whether the user was playing, returns true when that changed since the last update.
+async fn toggled_playing(session: Session, playback: Option<&PlaybackState>) -> bool {
+    let is_playing = playback.is_some_and(|p| p.is_playing);
+    let was_playing: Option<bool> = session.get("prev_is_playing").await.unwrap();
+    session.insert("prev_is_playing", is_playing).await.unwrap();
+    was_playing.is_some_and(|w| w != is_playing)
+}
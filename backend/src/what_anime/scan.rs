@@ -0,0 +1,214 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anilist_api::AnilistAPI;
+use anisong_api::AnisongAPI;
+use database_api::{
+    Database,
+    models::{LibraryScan, ScanHit},
+};
+use log::{error, info, warn};
+use spotify_api::{
+    SpotifyAPI,
+    models::{Error, TokenResponse, TrackObject},
+};
+use tokio::time::Duration;
+use what_anime_shared::SpotifyUserID;
+
+use super::{models::Anisongs, routes::AppState, routes::identify_track};
+
+const PAGE_SIZE: u32 = 50;
+const MAX_RETRIES: u32 = 5;
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Removes the user from the running scans, also when the scan task panics.
+struct RunningScan<'a> {
+    running: &'a std::sync::Mutex<std::collections::HashSet<SpotifyUserID>>,
+    user_id: SpotifyUserID,
+}
+
+impl Drop for RunningScan<'_> {
+    fn drop(&mut self) {
+        self.running.lock().unwrap().remove(&self.user_id);
+    }
+}
+
+/// Retries rate limited requests with a growing backoff, the spotify client already spaces
+/// out the requests themselves.
+async fn with_retries<T, F, Fut>(mut request: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut backoff = RATE_LIMIT_BACKOFF;
+    for _ in 0..MAX_RETRIES {
+        match request().await {
+            Err(Error::RateLimited) => {
+                warn!("Library scan rate limited, waiting {:?}", backoff);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+    request().await
+}
+
+async fn refresh_if_expired<S: SpotifyAPI>(
+    spotify_api: &S,
+    token: &mut TokenResponse,
+    client_id: spotify_api::models::ClientID,
+    client_secret: spotify_api::models::ClientSecret,
+) -> Result<(), Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+    if token.expires_in > now {
+        return Ok(());
+    }
+    let refresh = match token.refresh_token.clone() {
+        Some(r) => r,
+        None => return Err(Error::UnAuthorized),
+    };
+    let mut new = spotify_api
+        .refresh_token(refresh.clone(), client_id, client_secret)
+        .await?;
+    new.expires_in += now;
+    new.refresh_token = new.refresh_token.or(Some(refresh));
+    *token = new;
+    Ok(())
+}
+
+async fn match_tracks<D: Database>(
+    database: &D,
+    tracks: Vec<TrackObject>,
+    found_in: &str,
+    auto_bind: bool,
+) -> Vec<ScanHit> {
+    let mut hits = Vec::new();
+    for track in tracks {
+        let update = identify_track(database, &track, auto_bind).await;
+        if let Anisongs::Hit(hit) = &update.anisongs
+            && let Some(best) = hit.hits.first()
+        {
+            hits.push(ScanHit {
+                track_id: track.id.clone(),
+                track_name: track.name.clone(),
+                artist_names: track.artists.iter().map(|a| a.name.clone()).collect(),
+                song_id: best.song.id.expect("From database must be Some"),
+                anime_ann_id: best.anime.ann_id,
+                certainty: hit.certainty,
+                found_in: found_in.to_string(),
+            });
+        }
+    }
+    hits
+}
+
+/// Pages through the saved tracks and then every playlist of the user, saving progress after
+/// each page so a stopped scan continues where it left off.
+pub async fn run_library_scan<D, S, A, B>(
+    app_state: Arc<AppState<D, S, A, B>>,
+    mut token: TokenResponse,
+    mut scan: LibraryScan,
+) where
+    D: Database + Send + Sync + 'static,
+    S: SpotifyAPI + Send + Sync + 'static,
+    A: AnisongAPI + Send + Sync + 'static,
+    B: AnilistAPI + Send + Sync + 'static,
+{
+    let _running = RunningScan {
+        running: &app_state.library_scans,
+        user_id: scan.user_id.clone(),
+    };
+    let spotify = &app_state.spotify_api;
+
+    while !scan.finished {
+        if let Err(e) = refresh_if_expired(
+            spotify,
+            &mut token,
+            app_state.client_id.clone(),
+            app_state.client_secret.clone(),
+        )
+        .await
+        {
+            error!("Library scan stopped, token refresh failed: {:?}", e);
+            return;
+        }
+
+        let (tracks, found_in) = if let Some(offset) = scan.saved_offset {
+            let page = match with_retries(|| {
+                spotify.get_saved_tracks(token.access_token.clone(), offset as u32, PAGE_SIZE)
+            })
+            .await
+            {
+                Ok(p) => p,
+                Err(e) => {
+                    error!("Library scan stopped on saved tracks: {:?}", e);
+                    return;
+                }
+            };
+            scan.saved_offset = page.next.as_ref().map(|_| offset + PAGE_SIZE as i32);
+            let tracks: Vec<TrackObject> = page.items.into_iter().map(|s| s.track).collect();
+            (tracks, "saved".to_string())
+        } else {
+            let playlists = match with_retries(|| {
+                spotify.get_playlists(token.access_token.clone(), scan.playlist_offset as u32, 1)
+            })
+            .await
+            {
+                Ok(p) => p,
+                Err(e) => {
+                    error!("Library scan stopped on playlists: {:?}", e);
+                    return;
+                }
+            };
+            let playlist = match playlists.items.into_iter().next() {
+                Some(p) => p,
+                None => {
+                    scan.finished = true;
+                    app_state
+                        .database
+                        .save_library_scan(scan.clone(), vec![])
+                        .await;
+                    break;
+                }
+            };
+            let page = match with_retries(|| {
+                spotify.get_playlist_tracks(
+                    token.access_token.clone(),
+                    playlist.id.clone(),
+                    scan.track_offset as u32,
+                    PAGE_SIZE,
+                )
+            })
+            .await
+            {
+                Ok(p) => p,
+                Err(e) => {
+                    error!("Library scan stopped on playlist {}: {:?}", playlist.id, e);
+                    return;
+                }
+            };
+            if page.next.is_some() {
+                scan.track_offset += PAGE_SIZE as i32;
+            } else {
+                scan.playlist_offset += 1;
+                scan.track_offset = 0;
+            }
+            let tracks: Vec<TrackObject> = page.items.into_iter().filter_map(|p| p.track).collect();
+            (tracks, playlist.name)
+        };
+
+        scan.tracks_scanned += tracks.len() as i32;
+        let hits = match_tracks(&app_state.database, tracks, &found_in, scan.auto_bind).await;
+        app_state
+            .database
+            .save_library_scan(scan.clone(), hits)
+            .await;
+    }
+    info!("Library scan finished after {} tracks", scan.tracks_scanned);
+}
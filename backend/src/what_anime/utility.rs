@@ -2,17 +2,18 @@ use std::{collections::HashSet, f32};
 
 use anilist_api::AnilistAPI;
 use anisong_api::{AnisongAPI, models::Release};
-use chrono::Datelike;
-use database_api::{Database, models::DBAnisong};
+use database_api::{
+    Database,
+    models::DBAnisong,
+    season_import::{SeasonImportConfig, import_seasons},
+};
 use fuzzywuzzy;
 use kakasi;
-use log::error;
 use spotify_api::models::SimplifiedArtist;
 
 use database_api::regex::{
     normalize_text, process_artist_name, process_possible_japanese, process_similarity,
 };
-use what_anime_shared::ReleaseSeason;
 
 use super::models::NewSongHit;
 
@@ -102,6 +103,7 @@ pub fn select_best(
             NewSongHit {
                 hits: vec![],
                 more_by_artists: vec![],
+                more_by_artists_total: 0,
                 certainty: 0,
             },
             vec![],
@@ -140,6 +142,7 @@ pub fn select_best(
         NewSongHit {
             hits,
             more_by_artists,
+            more_by_artists_total: 0,
             certainty,
         },
         best_artist_pairs,
@@ -151,6 +154,7 @@ pub fn select_best_by_song_title(anisongs: Vec<DBAnisong>, song_title: &str) ->
         return NewSongHit {
             hits: vec![],
             more_by_artists: vec![],
+            more_by_artists_total: 0,
             certainty: 0,
         };
     }
@@ -168,55 +172,33 @@ pub fn select_best_by_song_title(anisongs: Vec<DBAnisong>, song_title: &str) ->
     NewSongHit {
         hits,
         more_by_artists,
+        more_by_artists_total: 0,
         certainty: best_score as i32,
     }
 }
 
-pub async fn update_current_season<D, A, B>(db: &D, anisong: &A, anilist: &B) -> u64
+/// Imports the current season and the `window - 1` before it, which still get late additions. A
+/// `window` of 0 imports nothing.
+pub async fn update_recent_seasons<D, A, B>(db: &D, anisong: &A, anilist: &B, window: u32) -> u64
 where
     D: Database + 'static + Send + Sync,
     A: AnisongAPI + 'static + Send + Sync,
     B: AnilistAPI + 'static + Send + Sync,
 {
-    let now = chrono::Local::now();
-    let year = now.year();
-    let month = now.month();
-    let season = match ReleaseSeason::try_from(month / 4) {
-        Ok(s) => s,
-        Err(e) => {
-            error!("How? Error: {:?}", e);
-            return 0;
-        }
-    };
-    let release = Release { season, year };
-    let anisongs = match anisong.get_anime_season(release).await {
-        Ok(a) => a,
-        Err(e) => {
-            error!("Failed anisong fetch! Error: {:?}", e);
-            return 0;
-        }
+    if window == 0 {
+        return 0;
+    }
+    let end = Release::current();
+    let start = (1..window).fold(end.clone(), |release, _| release.previous());
+    // Recent seasons keep growing, so they are imported again on every run.
+    let config = SeasonImportConfig {
+        resume: false,
+        max_attempts: 3,
+        ..SeasonImportConfig::new("hourly".to_string(), start, end)
     };
-    let ids: HashSet<what_anime_shared::AnilistAnimeID> = anisongs
+    import_seasons(db, anisong, anilist, config)
+        .await
         .iter()
-        .filter_map(|a| a.anime.linked_ids.anilist)
-        .collect();
-
-    let mut media: Vec<anilist_api::Media> = Vec::with_capacity(ids.len());
-    let mut ticker = tokio::time::interval(tokio::time::Duration::from_secs(1));
-    let ids: Vec<_> = ids.into_iter().collect();
-    for chunk in ids.chunks(50) {
-        ticker.tick().await;
-        let mut new = match anilist.fetch_many(chunk.to_vec()).await {
-            Ok(m) => m,
-            Err(e) => {
-                error!("Got error from anilist_api, Error {:?}", e);
-                vec![]
-            }
-        };
-        media.append(&mut new);
-    }
-
-    let numof = anisongs.len();
-    db.add_from_anisongs(anisongs, media).await;
-    numof as u64
+        .map(|i| i.songs as u64)
+        .sum()
 }
@@ -0,0 +1,243 @@
+//! Response cache shared by the caching decorators of the upstream api crates.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+#[derive(Debug, Clone)]
+pub enum CacheStore {
+    Memory,
+    /// Keeps a json file per entry in the directory, so entries survive restarts.
+    Disk(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub ttl: Duration,
+    /// Oldest entries are evicted past this, for both the memory and the disk store.
+    pub max_entries: usize,
+    pub store: CacheStore,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    stored_at: u64,
+    value: Value,
+}
+
+/// Disk evictions remove this share of `max_entries` at once, so the directory is only listed
+/// once per batch instead of on every insert.
+const DISK_EVICTION_DIVISOR: usize = 10;
+
+pub struct ResponseCache {
+    config: CacheConfig,
+    entries: Mutex<HashMap<String, Entry>>,
+    /// Files in the disk store.
+    disk_entries: AtomicUsize,
+    /// Set while a disk eviction runs, so only one lists the directory at a time.
+    evicting: AtomicBool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+/// FNV-1a, file names have to stay the same between builds which `DefaultHasher` doesn't promise.
+fn file_name(key: &str) -> String {
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}.json", hash)
+}
+
+/// Removes the oldest files until `keep` are left, returns how many were removed. Blocking.
+fn evict_oldest(dir: &Path, keep: usize) -> usize {
+    let mut files: Vec<(SystemTime, PathBuf)> = match std::fs::read_dir(dir) {
+        Ok(files) => files
+            .filter_map(|f| f.ok())
+            .filter_map(|f| Some((f.metadata().ok()?.modified().ok()?, f.path())))
+            .collect(),
+        Err(_) => return 0,
+    };
+    if files.len() <= keep {
+        return 0;
+    }
+    files.sort();
+    let excess = files.len() - keep;
+    files[..excess]
+        .iter()
+        .filter(|(_, path)| std::fs::remove_file(path).is_ok())
+        .count()
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> Self {
+        let mut disk_entries = 0;
+        if let CacheStore::Disk(dir) = &config.store {
+            std::fs::create_dir_all(dir).expect("Cache directory must be creatable");
+            disk_entries = std::fs::read_dir(dir)
+                .expect("Cache directory must be readable")
+                .count();
+        }
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            disk_entries: AtomicUsize::new(disk_entries),
+            evicting: AtomicBool::new(false),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn is_fresh(&self, entry: &Entry) -> bool {
+        now().saturating_sub(entry.stored_at) < self.config.ttl.as_secs()
+    }
+
+    async fn read_disk(&self, key: &str) -> Option<Entry> {
+        let CacheStore::Disk(dir) = &self.config.store else {
+            return None;
+        };
+        let text = tokio::fs::read_to_string(dir.join(file_name(key)))
+            .await
+            .ok()?;
+        let entry: Entry = serde_json::from_str(&text).ok()?;
+        // Hash collisions just count as a miss.
+        (entry.key == key).then_some(entry)
+    }
+
+    async fn write_disk(&self, entry: &Entry) {
+        let CacheStore::Disk(dir) = &self.config.store else {
+            return;
+        };
+        let path = dir.join(file_name(&entry.key));
+        let is_new = !tokio::fs::try_exists(&path).await.unwrap_or(false);
+        let text = serde_json::to_string(entry).unwrap();
+        if let Err(e) = tokio::fs::write(path, text).await {
+            warn!("Failed to write cache entry: {:?}", e);
+            return;
+        }
+        if !is_new {
+            return;
+        }
+
+        let disk_entries = self.disk_entries.fetch_add(1, Ordering::Relaxed) + 1;
+        // Writes during a running eviction only add to the count, the next one catches up.
+        if disk_entries <= self.config.max_entries || self.evicting.swap(true, Ordering::Acquire) {
+            return;
+        }
+        let keep = self.config.max_entries - self.config.max_entries / DISK_EVICTION_DIVISOR;
+        let dir = dir.clone();
+        let removed = tokio::task::spawn_blocking(move || evict_oldest(&dir, keep))
+            .await
+            .unwrap_or(0);
+        let _ = self
+            .disk_entries
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| {
+                Some(c.saturating_sub(removed))
+            });
+        self.evictions.fetch_add(removed as u64, Ordering::Relaxed);
+        self.evicting.store(false, Ordering::Release);
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let in_memory = self.entries.lock().unwrap().contains_key(key);
+        // Read without holding the lock, so a slow disk doesn't block every other lookup.
+        let from_disk = if in_memory {
+            None
+        } else {
+            self.read_disk(key).await
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = from_disk {
+            entries.entry(key.to_string()).or_insert(entry);
+        }
+        let value = entries
+            .get(key)
+            .filter(|e| self.is_fresh(e))
+            .and_then(|e| serde_json::from_value(e.value.clone()).ok());
+        match value {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        value
+    }
+
+    pub async fn insert<T: Serialize>(&self, key: &str, value: &T) {
+        let entry = Entry {
+            key: key.to_string(),
+            stored_at: now(),
+            value: serde_json::to_value(value).unwrap(),
+        };
+        self.write_disk(&entry).await;
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.to_string(), entry);
+        entries.retain(|_, e| self.is_fresh(e));
+        while entries.len() > self.config.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, e)| e.stored_at)
+                .map(|(k, _)| k.clone())
+                .expect("Can't be empty while over the limit");
+            entries.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Only successful fetches are cached.
+    pub async fn get_or_fetch<T, E, F, Fut>(&self, key: String, fetch: F) -> Result<T, E>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        if let Some(value) = self.get(&key).await {
+            return Ok(value);
+        }
+        let value = fetch().await?;
+        self.insert(&key, &value).await;
+        Ok(value)
+    }
+}
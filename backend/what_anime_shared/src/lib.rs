@@ -1,6 +1,8 @@
 use std::str::FromStr;
+pub mod cache;
 pub mod error;
 
+use chrono::Datelike;
 use serde::{Deserialize, Serialize};
 use sqlx::{
     FromRow, Type,
@@ -26,6 +28,18 @@ impl std::fmt::Display for SpotifyTrackID {
     }
 }
 
+/// International Standard Recording Code, shared by every spotify track of the same recording.
+#[derive(
+    Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize, Hash, FromRow, Type,
+)]
+#[sqlx(transparent)]
+pub struct Isrc(pub String);
+impl std::fmt::Display for Isrc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 #[derive(
     Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize, Hash, FromRow, Type,
 )]
@@ -60,8 +74,13 @@ pub struct SpotifyUser {
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize, Hash, Type)]
 #[sqlx(transparent)]
 pub struct SpotifyUserID(String);
+impl std::fmt::Display for SpotifyUserID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ReleaseSeason {
     Winter,
@@ -141,3 +160,145 @@ impl FromStr for ReleaseSeason {
 
     type Err = ();
 }
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Release {
+    pub season: ReleaseSeason,
+    pub year: i32,
+}
+
+impl Release {
+    /// The season airing in `month`, counted from 1. Winter is January to March.
+    pub fn from_month(year: i32, month: u32) -> Self {
+        assert!((1..=12).contains(&month), "Month must be in 1..=12");
+        Self {
+            season: ReleaseSeason::try_from((month - 1) / 3).unwrap(),
+            year,
+        }
+    }
+    /// The season airing right now, in UTC.
+    pub fn current() -> Self {
+        let now = chrono::Utc::now();
+        Self::from_month(now.year(), now.month())
+    }
+    pub fn next(&self) -> Self {
+        match self.season {
+            ReleaseSeason::Fall => Self {
+                season: ReleaseSeason::Winter,
+                year: self.year + 1,
+            },
+            season => Self {
+                season: ReleaseSeason::try_from(season as u32 + 1).unwrap(),
+                year: self.year,
+            },
+        }
+    }
+    pub fn previous(&self) -> Self {
+        match self.season {
+            ReleaseSeason::Winter => Self {
+                season: ReleaseSeason::Fall,
+                year: self.year - 1,
+            },
+            season => Self {
+                season: ReleaseSeason::try_from(season as u32 - 1).unwrap(),
+                year: self.year,
+            },
+        }
+    }
+}
+
+impl PartialOrd for Release {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Chronological, by year and then season.
+impl Ord for Release {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.year, self.season).cmp(&(other.year, other.season))
+    }
+}
+
+impl std::fmt::Display for Release {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.season, self.year)
+    }
+}
+
+/// Accepts anisongdb's `"Spring 2008"` as well as the serialized form.
+impl<'de> Deserialize<'de> for Release {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Upstream(String),
+            Serialized { season: ReleaseSeason, year: i32 },
+        }
+        let s = match Repr::deserialize(deserializer)? {
+            Repr::Upstream(s) => s,
+            Repr::Serialized { season, year } => return Ok(Self { season, year }),
+        };
+        let parsed = s.trim().rsplit_once(' ').and_then(|(season, year)| {
+            Some(Self {
+                season: ReleaseSeason::from_str(season.trim()).ok()?,
+                year: year.parse().ok()?,
+            })
+        });
+        parsed.ok_or_else(|| {
+            serde::de::Error::custom(format!("Failed to parse Release from string: {}", s))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_from_month() {
+        let seasons: Vec<ReleaseSeason> = (1..=12)
+            .map(|month| Release::from_month(2024, month).season)
+            .collect();
+        use ReleaseSeason::*;
+        assert_eq!(
+            seasons,
+            vec![
+                Winter, Winter, Winter, Spring, Spring, Spring, Summer, Summer, Summer, Fall, Fall,
+                Fall
+            ]
+        );
+    }
+
+    #[test]
+    fn test_release_previous() {
+        let winter = Release {
+            season: ReleaseSeason::Winter,
+            year: 2024,
+        };
+        let fall = winter.previous();
+        assert_eq!(fall.season, ReleaseSeason::Fall);
+        assert_eq!(fall.year, 2023);
+        assert_eq!(fall.next(), winter);
+        let summer = Release {
+            season: ReleaseSeason::Summer,
+            year: 2024,
+        };
+        assert_eq!(summer.previous().season, ReleaseSeason::Spring);
+        assert_eq!(summer.previous().next(), summer);
+    }
+
+    #[test]
+    fn test_release_parse() {
+        let release: Release = serde_json::from_str("\"Spring 2008\"").unwrap();
+        assert_eq!(release.season, ReleaseSeason::Spring);
+        assert_eq!(release.year, 2008);
+        let roundtrip: Release =
+            serde_json::from_str(&serde_json::to_string(&release).unwrap()).unwrap();
+        assert_eq!(roundtrip, release);
+        assert!(serde_json::from_str::<Release>("\"2008\"").is_err());
+    }
+}
@@ -6,8 +6,11 @@ export interface SongInfo {
     romanized_song_name: string,
     song_artists: string[]; // List of song artists
     romanized_artists: string[],
-    album_image: string; // URL for the album image
-    spotify_song_id: string; // Spotify track ID
+    album_name?: string | null;
+    album_image: string | null; // URL for the album image
+    duration_ms?: number;
+    spotify_song_id: string | null; // Spotify track ID, null for local files
+    spotify_url?: string | null;
 }
 
 interface SongConProps {
@@ -89,17 +89,34 @@ const Update = () => {
                         case "no_updates":
                         case "not_playing":
                     }
+                } else if ("playback" in data) {
+                    // Same track, nothing shown depends on the play state.
+                } else if ("playing_episode" in data) {
+                    const episode = data.playing_episode;
+                    setInfo({
+                        song_info: {
+                            song_name: episode.name,
+                            romanized_song_name: episode.name,
+                            song_artists: [episode.show_name],
+                            romanized_artists: [episode.show_name],
+                            album_image: episode.image,
+                            spotify_song_id: null,
+                        },
+                        anisongs: { miss: { possible: [] } }
+                    });
                 } else {
                     setInfo(data.new_song);
                     const anisongs = data.new_song.anisongs;
-                    let show_button = true;
-                    if ("hit" in anisongs) {
+                    const spotify_song_id = data.new_song.song_info.spotify_song_id;
+                    // local files can't be bound, they have no spotify id
+                    let show_button = spotify_song_id !== null;
+                    if (show_button && "hit" in anisongs) {
                         show_button = !(anisongs.hit.certainty === 100);
                     }
                     setListConfig((p) => ({
                         ...p,
                         show_confirm_button: show_button,
-                        spotify_song_id: data.new_song.song_info.spotify_song_id,
+                        spotify_song_id: spotify_song_id ?? "",
                     }))
                 }
             })
@@ -118,7 +135,7 @@ const Update = () => {
         <>
             {reportOverlay.show && (
                 <ReportButton
-                    track_id={info.song_info.spotify_song_id}
+                    track_id={info.song_info.spotify_song_id ?? ""}
                     hide={() => setReportOverlay((p) => ({ ...p, show: false, }))}
                     ann_song_id={reportOverlay.song_ann_id}>
                 </ReportButton>
@@ -163,7 +180,34 @@ export type Update =
     | "login_required"
     | "unauthorized"
     | "not_playing"
-    | { new_song: SongUpdate };
+    | { new_song: NowPlaying }
+    | { playback: PlaybackState | null }
+    | { playing_episode: EpisodeInfo };
+
+export interface NowPlaying extends SongUpdate {
+    playback: PlaybackState | null; // null when spotify reports no active device
+}
+
+export interface PlaybackState {
+    progress_ms: number | null;
+    is_playing: boolean;
+    shuffle_state: boolean;
+    repeat_state: "off" | "track" | "context";
+    device: Device | null;
+}
+
+export interface Device {
+    id: string | null;
+    name: string;
+    type: string;
+    volume_percent: number | null;
+}
+
+export interface EpisodeInfo {
+    name: string;
+    show_name: string;
+    image: string | null;
+}
 
 export interface SongUpdate {
     song_info: SongInfo; // Information about the current song
@@ -1,53 +1,20 @@
-use anilist_api::{AnilistAPI, AnilistAPIR};
-use anisong_api::{
-    AnisongAPI, AnisongAPIR,
-    models::{AnisongAnime, AnisongArtistID, AnisongBind, AnisongSong, Release},
-};
+use anilist_api::{AnilistAPIR, CachedAnilistAPI};
+use anisong_api::{AnisongAPIR, CachedAnisongAPI, models::Release};
 use chrono::Datelike;
 use database_api::{
-    Database, DatabaseR,
-    models::{DBAnime, DBAnisongBind, SimplifiedAnisongSong},
+    DatabaseR,
+    season_import::{SeasonImportConfig, import_seasons},
 };
 use dotenvy;
-use std::{
-    collections::{HashMap, HashSet},
-    io,
+use std::{io, path::PathBuf, time::Duration};
+use what_anime_shared::{
+    ReleaseSeason,
+    cache::{CacheConfig, CacheStore},
 };
-use what_anime_shared::{AnilistAnimeID, ReleaseSeason};
-
-async fn scrape_season(
-    release: Release,
-    anisong: &AnisongAPIR,
-    anilist: &AnilistAPIR,
-    db: &DatabaseR,
-) -> usize {
-    let anisongs = anisong.get_anime_season(release).await.unwrap();
-    let song_amount = anisongs.len();
 
-    let ids: HashSet<what_anime_shared::AnilistAnimeID> = anisongs
-        .iter()
-        .filter_map(|a| a.anime.linked_ids.anilist)
-        .collect();
-    let mut media: Vec<anilist_api::Media> = Vec::with_capacity(ids.len());
-
-    let mut ticker = tokio::time::interval(tokio::time::Duration::from_secs(1));
-    let ids: Vec<_> = ids.into_iter().collect();
-    for chunk in ids.chunks(50) {
-        ticker.tick().await;
-        let mut new = match anilist.fetch_many(chunk.to_vec()).await {
-            Ok(m) => m,
-            Err(e) => {
-                log::error!("Got error from anilist_api, Error {:?}", e);
-                vec![]
-            }
-        };
-        media.append(&mut new);
-    }
-
-    db.add_from_anisongs(anisongs, media).await;
-    song_amount
-    // todo!()
-}
+/// Repeated runs over the same seasons are served from here instead of the upstream apis.
+pub const CACHE_DIR: &str = "../cache";
+pub const CACHE_TTL: Duration = Duration::from_secs(60 * 60 * 24);
 
 pub async fn fetch_anisong() -> bool {
     let year = chrono::Local::now().year();
@@ -88,7 +55,7 @@ pub async fn fetch_anisong() -> bool {
     io::stdin()
         .read_line(&mut inp)
         .expect("Failed to parse input");
-    let mut start_year: u32 = match inp.trim().parse() {
+    let start_year: u32 = match inp.trim().parse() {
         Ok(v) => v,
         Err(_) => {
             eprintln!("Invalid input, exiting...");
@@ -152,40 +119,81 @@ pub async fn fetch_anisong() -> bool {
         return false;
     }
 
-    let anisong = AnisongAPIR::new();
-    let anilist = AnilistAPIR::new();
-    let db = DatabaseR::new(1).await;
-
-    let seasons = [
-        ReleaseSeason::Winter,
-        ReleaseSeason::Spring,
-        ReleaseSeason::Summer,
-        ReleaseSeason::Fall,
-    ];
     start_season -= 1;
     end_season -= 1;
+    let start = Release {
+        season: ReleaseSeason::try_from(start_season).unwrap(),
+        year: start_year as i32,
+    };
+    let end = Release {
+        season: ReleaseSeason::try_from(end_season).unwrap(),
+        year: end_year as i32,
+    };
+    let config = SeasonImportConfig {
+        resume: false,
+        ..SeasonImportConfig::new("manual".to_string(), start, end)
+    };
+    run_import(config).await
+}
+
+/// Imports every season up to the current one without prompting, continuing after the last
+/// season a previous run completed.
+pub async fn resume_anisong_import() -> bool {
+    match dotenvy::from_path("../dev.env") {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            panic!();
+        }
+    };
+    let end = Release::current();
     // Fall 1959 has something
-    let mut ticker = tokio::time::interval(tokio::time::Duration::from_secs(6));
-    loop {
-        let release = Release {
-            season: seasons[start_season as usize].clone(),
-            year: start_year as i32,
-        };
-        let found_anime = scrape_season(release.clone(), &anisong, &anilist, &db).await;
-        println!(
-            "Fetched {} animes from anisong release {}",
-            found_anime, release
-        );
-        if start_season == end_season && start_year == end_year {
-            println!("Data fetch done!");
-            return true;
-        } else {
-            start_season += 1;
-            if start_season == 4 {
-                start_season = 0;
-                start_year += 1;
-            }
+    let start = Release {
+        season: ReleaseSeason::Winter,
+        year: 1951,
+    };
+    run_import(SeasonImportConfig::new("full".to_string(), start, end)).await
+}
+
+async fn run_import(config: SeasonImportConfig) -> bool {
+    let anisong = CachedAnisongAPI::new(
+        AnisongAPIR::from_env(),
+        CacheConfig {
+            ttl: CACHE_TTL,
+            max_entries: 1000,
+            store: CacheStore::Disk(PathBuf::from(CACHE_DIR).join("anisong")),
+        },
+    );
+    let anilist = CachedAnilistAPI::new(
+        AnilistAPIR::new(),
+        CacheConfig {
+            ttl: CACHE_TTL,
+            max_entries: 50000,
+            store: CacheStore::Disk(PathBuf::from(CACHE_DIR).join("anilist")),
+        },
+    );
+    let db = DatabaseR::new(1).await;
+
+    let end = config.end.clone();
+    let imports = import_seasons(&db, &anisong, &anilist, config).await;
+    for import in &imports {
+        match &import.error {
+            None => println!(
+                "{} {}: {} songs in {} attempts, {:?}",
+                import.season, import.year, import.songs, import.attempts, import.summary
+            ),
+            Some(e) => println!(
+                "{} {}: failed after {} attempts, {}",
+                import.season, import.year, import.attempts, e
+            ),
         }
-        ticker.tick().await;
     }
+    println!(
+        "Cache hits anisong: {:?} anilist: {:?}",
+        anisong.stats(),
+        anilist.stats()
+    );
+    imports
+        .last()
+        .is_none_or(|i| i.error.is_none() && i.season == end.season && i.year == end.year)
 }
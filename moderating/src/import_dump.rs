@@ -0,0 +1,59 @@
+use std::{io, path::PathBuf};
+
+use anilist_api::{AnilistAPIR, CachedAnilistAPI};
+use database_api::{DatabaseR, dump::import_dump};
+use what_anime_shared::cache::{CacheConfig, CacheStore};
+
+use crate::fetch_anisong::{CACHE_DIR, CACHE_TTL};
+
+const CHUNK_SIZE: usize = 500;
+
+/// Seeds the database from a json or json lines dump of anisongdb records.
+pub async fn import_anisong_dump() -> bool {
+    match dotenvy::from_path("../dev.env") {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    };
+    println!("Input path of the dump file\n");
+    let mut inp = String::new();
+    io::stdin()
+        .read_line(&mut inp)
+        .expect("Failed to parse input");
+    let path = PathBuf::from(inp.trim());
+    if !path.is_file() {
+        eprintln!("No file at {}, exiting...", path.display());
+        return false;
+    }
+
+    let anilist = CachedAnilistAPI::new(
+        AnilistAPIR::new(),
+        CacheConfig {
+            ttl: CACHE_TTL,
+            max_entries: 50000,
+            store: CacheStore::Disk(PathBuf::from(CACHE_DIR).join("anilist")),
+        },
+    );
+    let db = DatabaseR::new(1).await;
+
+    let result = import_dump(&db, &anilist, path, CHUNK_SIZE, |progress| {
+        println!(
+            "Imported {} records in {} chunks",
+            progress.records, progress.chunks
+        )
+    })
+    .await;
+    match result {
+        Ok(done) => {
+            println!("Dump import done: {:?}", done.summary);
+            println!("Cache hits anilist: {:?}", anilist.stats());
+            true
+        }
+        Err(e) => {
+            eprintln!("Dump import stopped: {:?}", e);
+            false
+        }
+    }
+}
@@ -1,14 +1,29 @@
 mod fetch_anisong;
+mod import_dump;
 mod load_links;
 mod parse_reports;
+mod refresh_view;
+mod snapshot;
 
 use std::io::Read;
 
-use fetch_anisong::fetch_anisong;
+use fetch_anisong::{fetch_anisong, resume_anisong_import};
+use import_dump::import_anisong_dump;
 use load_links::load_links;
 use log::{error, info, warn};
 use parse_reports::parse_reports;
-const OPTIONS: &'static [&str] = &["Run anisong fetch", "Load links", "Parse Reports"];
+use refresh_view::refresh_view;
+use snapshot::{export_database, import_database};
+const OPTIONS: &'static [&str] = &[
+    "Run anisong fetch",
+    "Load links",
+    "Parse Reports",
+    "Refresh anisong view",
+    "Resume full anisong import",
+    "Import anisong dump",
+    "Export database snapshot",
+    "Import database snapshot",
+];
 
 #[tokio::main]
 async fn main() {
@@ -50,6 +65,41 @@ async fn main() {
                 warn!("Something went wrong while parsing reports")
             }
         }
+        "4" => {
+            if refresh_view().await {
+                info!("Refreshed anisong view")
+            } else {
+                warn!("Failed to refresh anisong view")
+            }
+        }
+        "5" => {
+            if resume_anisong_import().await {
+                info!("Anisong import is up to date")
+            } else {
+                warn!("Anisong import stopped, run it again to resume")
+            }
+        }
+        "6" => {
+            if import_anisong_dump().await {
+                info!("Imported anisong dump")
+            } else {
+                warn!("Failed to import anisong dump")
+            }
+        }
+        "7" => {
+            if export_database().await {
+                info!("Exported database snapshot")
+            } else {
+                warn!("Failed to export database snapshot")
+            }
+        }
+        "8" => {
+            if import_database().await {
+                info!("Imported database snapshot")
+            } else {
+                warn!("Failed to import database snapshot")
+            }
+        }
         _ => {
             error!("invalid input");
         }
@@ -0,0 +1,14 @@
+use database_api::{Database, DatabaseR};
+
+pub async fn refresh_view() -> bool {
+    match dotenvy::from_path("../dev.env") {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    };
+    let db = DatabaseR::new(1).await;
+    println!("Refreshed rows: {}", db.refresh_anisong_view().await);
+    true
+}
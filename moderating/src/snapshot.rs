@@ -0,0 +1,79 @@
+use std::{io, path::PathBuf};
+
+use database_api::{
+    DatabaseR,
+    snapshot::{SnapshotFormat, export_snapshot, import_snapshot},
+};
+
+fn read_input(prompt: &str) -> String {
+    println!("{}\n", prompt);
+    let mut inp = String::new();
+    io::stdin()
+        .read_line(&mut inp)
+        .expect("Failed to parse input");
+    inp.trim().to_string()
+}
+
+/// Writes the anisong data and the spotify links to a snapshot directory.
+pub async fn export_database() -> bool {
+    match dotenvy::from_path("../dev.env") {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    };
+    let dir = PathBuf::from(read_input("Input snapshot directory"));
+    let format = match read_input("Input format\n1: jsonl\n2: csv").as_str() {
+        "1" => SnapshotFormat::Jsonl,
+        "2" => SnapshotFormat::Csv,
+        _ => {
+            eprintln!("Invalid input, exiting...");
+            return false;
+        }
+    };
+
+    let db = DatabaseR::new(1).await;
+    match export_snapshot(&db, &dir, format).await {
+        Ok(manifest) => {
+            for table in manifest.tables {
+                println!("{}: {} rows", table.file, table.rows);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("Export failed: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Restores a snapshot made by `export_database` into an empty database.
+pub async fn import_database() -> bool {
+    match dotenvy::from_path("../dev.env") {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    };
+    let dir = PathBuf::from(read_input("Input snapshot directory"));
+    if !dir.is_dir() {
+        eprintln!("No directory at {}, exiting...", dir.display());
+        return false;
+    }
+
+    let db = DatabaseR::new(1).await;
+    match import_snapshot(&db, &dir).await {
+        Ok(inserted) => {
+            for (table, rows) in inserted {
+                println!("{}: {} rows added", table.name(), rows);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("Import failed: {:?}", e);
+            false
+        }
+    }
+}